@@ -0,0 +1,114 @@
+// Intcode VM C API (generated from src/ffi.rs by tests/ffi.rs, do not edit)
+
+#ifndef INTCODE_H
+#define INTCODE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Virtual machine handle
+typedef struct IntcodeVm IntcodeVm;
+
+// Result of stepping or running a virtual machine
+typedef enum {
+    // Instruction executed
+    INTCODE_OK = 0,
+    // Output value produced (see `intcode_pop_output`)
+    INTCODE_OUTPUT = 1,
+    // Input value needed to continue (see `intcode_push_input`)
+    INTCODE_NEED_INPUT = 2,
+    // Program halted
+    INTCODE_HALTED = 3,
+    // Program failed (see `intcode_last_error`)
+    INTCODE_ERROR = -1,
+} IntcodeStatus;
+
+// Create a virtual machine with a program of the given values
+//
+// Safety
+//
+// `values` must point to `len` values. The returned handle must be freed with `intcode_free`.
+IntcodeVm *intcode_new(const int64_t *values, size_t len);
+
+// Free a virtual machine
+//
+// Safety
+//
+// `vm` must be a handle returned by `intcode_new` (or null) and must not be used afterwards.
+void intcode_free(IntcodeVm *vm);
+
+// Push an input value
+//
+// Safety
+//
+// `vm` must be a valid handle.
+void intcode_push_input(IntcodeVm *vm, int64_t value);
+
+// Execute one instruction
+//
+// Safety
+//
+// `vm` must be a valid handle.
+IntcodeStatus intcode_step(IntcodeVm *vm);
+
+// Execute instructions until an output value is produced, input is needed, or the program
+// halts or fails
+//
+// Safety
+//
+// `vm` must be a valid handle.
+IntcodeStatus intcode_run(IntcodeVm *vm);
+
+// Take the oldest output value that wasn't read yet. Returns false if there is none.
+//
+// Safety
+//
+// `vm` must be a valid handle and `value` must point to writable memory.
+bool intcode_pop_output(IntcodeVm *vm, int64_t *value);
+
+// Return the memory size
+//
+// Safety
+//
+// `vm` must be a valid handle.
+size_t intcode_memory_size(const IntcodeVm *vm);
+
+// Read the value at the given address. Returns false if the address is out of range.
+//
+// Safety
+//
+// `vm` must be a valid handle and `value` must point to writable memory.
+bool intcode_read(const IntcodeVm *vm, size_t addr, int64_t *value);
+
+// Write a value to the given address. Returns false if the address is out of range.
+//
+// Safety
+//
+// `vm` must be a valid handle.
+bool intcode_write(IntcodeVm *vm, size_t addr, int64_t value);
+
+// Return the instruction pointer
+//
+// Safety
+//
+// `vm` must be a valid handle.
+size_t intcode_ip(const IntcodeVm *vm);
+
+// Return the message of the last error (or null). The message is valid until the handle is
+// freed or the next error occurs.
+//
+// Safety
+//
+// `vm` must be a valid handle.
+const char *intcode_last_error(const IntcodeVm *vm);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
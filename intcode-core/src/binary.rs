@@ -0,0 +1,260 @@
+//! Advent of Code 2019: Intcode binary memory format
+//!
+//! Memory images are stored in a compact binary format:
+//!
+//! - magic bytes `ICMB` followed by the format version (one byte, currently 1)
+//! - number of words (unsigned LEB128 varint)
+//! - all words as zigzag encoded (signed) LEB128 varints
+//! - CRC-32 checksum of all preceding bytes (4 bytes, little endian)
+//!
+//! Small values (which are most common in Intcode programs) only take one byte, so images are
+//! usually less than half the size of the comma separated text format.
+
+use crate::memory::{Address, Memory};
+use crate::word::Word;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Magic bytes at the start of a binary memory image
+const MAGIC: &[u8; 4] = b"ICMB";
+
+/// Version of the binary format
+const VERSION: u8 = 1;
+
+/// Error returned when encoding or decoding a binary memory image fails
+#[derive(Debug, PartialEq, Eq)]
+pub enum BinaryError {
+    /// Data doesn't start with the magic bytes of a binary memory image
+    InvalidMagic,
+    /// Image uses a format version that isn't supported
+    UnsupportedVersion(u8),
+    /// Data ends before the image is complete
+    Truncated,
+    /// Varint encoding is invalid (too long for a 64 bit value)
+    InvalidVarint(usize),
+    /// Checksum doesn't match the content
+    ChecksumMismatch {
+        /// Checksum stored in the image
+        expected: u32,
+        /// Checksum of the content
+        found: u32,
+    },
+    /// Data continues after the end of the image
+    TrailingData,
+    /// Value doesn't fit into the word type (or into 64 bits when encoding)
+    OutOfRange(Address),
+}
+
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryError::InvalidMagic => write!(f, "Not a binary Intcode memory image"),
+            BinaryError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported binary format version {}", version)
+            }
+            BinaryError::Truncated => write!(f, "Binary memory image is truncated"),
+            BinaryError::InvalidVarint(offset) => {
+                write!(f, "Invalid varint at byte offset {}", offset)
+            }
+            BinaryError::ChecksumMismatch { expected, found } => write!(
+                f,
+                "Checksum mismatch (expected {:08x}, found {:08x})",
+                expected, found
+            ),
+            BinaryError::TrailingData => write!(f, "Unexpected data after binary memory image"),
+            BinaryError::OutOfRange(addr) => write!(f, "Value at address {} is out of range", addr),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BinaryError {}
+
+/// CRC-32 (IEEE) lookup table
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+};
+
+/// Calculate CRC-32 (IEEE) checksum
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, byte| {
+        CRC_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Append unsigned LEB128 varint
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read unsigned LEB128 varint at the given position and advance the position
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64, BinaryError> {
+    let start = *pos;
+    let mut value = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or(BinaryError::Truncated)?;
+        *pos += 1;
+        let bits = u64::from(byte & 0x7f);
+        if shift == 63 && bits > 1 {
+            return Err(BinaryError::InvalidVarint(start));
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(BinaryError::InvalidVarint(start))
+}
+
+/// Zigzag encode signed value (so that small negative values have short encodings)
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Decode zigzag encoded value
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+impl<V: Word> Memory<V> {
+    /// Return true if the given data starts like a binary memory image (rather than text)
+    pub fn is_binary(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Encode memory into the binary format
+    ///
+    /// Fails if a value doesn't fit into 64 bits.
+    pub fn to_binary(&self) -> Result<Vec<u8>, BinaryError> {
+        let mut out = Vec::with_capacity(MAGIC.len() + 1 + 10 + self.size() + 4);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        write_varint(&mut out, self.size() as u64);
+        for (addr, value) in self.iter().enumerate() {
+            let value = value.to_i64().ok_or(BinaryError::OutOfRange(addr))?;
+            write_varint(&mut out, zigzag(value));
+        }
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        Ok(out)
+    }
+
+    /// Decode memory from the binary format
+    pub fn from_binary(data: &[u8]) -> Result<Self, BinaryError> {
+        if !data.starts_with(MAGIC) {
+            return Err(BinaryError::InvalidMagic);
+        }
+        let mut pos = MAGIC.len();
+        let version = *data.get(pos).ok_or(BinaryError::Truncated)?;
+        if version != VERSION {
+            return Err(BinaryError::UnsupportedVersion(version));
+        }
+        pos += 1;
+        let size = read_varint(data, &mut pos)?;
+        // Every word takes at least one byte, so don't trust larger sizes for preallocation
+        let mut words = Vec::with_capacity(size.min(data.len() as u64) as usize);
+        for addr in 0..size as usize {
+            let value = unzigzag(read_varint(data, &mut pos)?);
+            words.push(V::from_i64(value).ok_or(BinaryError::OutOfRange(addr))?);
+        }
+        let checksum = data.get(pos..pos + 4).ok_or(BinaryError::Truncated)?;
+        let expected = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        let found = crc32(&data[..pos]);
+        if expected != found {
+            return Err(BinaryError::ChecksumMismatch { expected, found });
+        }
+        if data.len() > pos + 4 {
+            return Err(BinaryError::TrailingData);
+        }
+        Ok(Memory::from(words))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+    use alloc::vec;
+
+    #[test]
+    fn varints() {
+        for value in [0, 1, -1, 63, -64, 64, 1_000_000, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+            let mut out = Vec::new();
+            write_varint(&mut out, zigzag(value));
+            let mut pos = 0;
+            assert_eq!(read_varint(&out, &mut pos), Ok(zigzag(value)));
+            assert_eq!(pos, out.len());
+        }
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        let mut pos = 0;
+        assert_eq!(
+            read_varint(&[0xff; 11], &mut pos),
+            Err(BinaryError::InvalidVarint(0))
+        );
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let memory = Memory::<i64>::from(vec![1002, 4, 3, 4, -33, i64::MIN, i64::MAX]);
+        let data = memory.to_binary().unwrap();
+        assert_eq!(&data[..7], b"ICMB\x01\x07\xd4");
+        assert_eq!(Memory::<i64>::from_binary(&data).unwrap(), memory);
+        assert_eq!(
+            Memory::<Value>::from_binary(&data),
+            Err(BinaryError::OutOfRange(5))
+        );
+    }
+
+    #[test]
+    fn invalid_data() {
+        let memory = Memory::<Value>::from(vec![1, 2, 3]);
+        let mut data = memory.to_binary().unwrap();
+        assert_eq!(
+            Memory::<Value>::from_binary(b"1,2,3"),
+            Err(BinaryError::InvalidMagic)
+        );
+        assert_eq!(
+            Memory::<Value>::from_binary(&data[..data.len() - 1]),
+            Err(BinaryError::Truncated)
+        );
+        data.push(0);
+        assert_eq!(
+            Memory::<Value>::from_binary(&data),
+            Err(BinaryError::TrailingData)
+        );
+        data.pop();
+        data[6] = 4;
+        assert!(matches!(
+            Memory::<Value>::from_binary(&data),
+            Err(BinaryError::ChecksumMismatch { .. })
+        ));
+        data[4] = 2;
+        assert_eq!(
+            Memory::<Value>::from_binary(&data),
+            Err(BinaryError::UnsupportedVersion(2))
+        );
+    }
+}
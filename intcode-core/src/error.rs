@@ -0,0 +1,142 @@
+//! Advent of Code 2019: Intcode errors
+
+use crate::memory::Address;
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::fmt;
+
+/// Error returned when running an Intcode program fails
+#[derive(Debug)]
+pub enum Error {
+    /// Instruction can't be decoded (e.g. because of an invalid parameter mode)
+    InvalidInstruction {
+        /// Address of the failing instruction
+        ip: Address,
+        /// Value of the instruction
+        instruction: String,
+    },
+    /// Instruction uses an opcode that is neither builtin nor provided by an extension
+    UnknownOpcode {
+        /// Address of the failing instruction
+        ip: Address,
+        /// Opcode of the instruction
+        opcode: i64,
+    },
+    /// Instruction accesses an invalid address (e.g. a negative address or an address past the
+    /// end of memory), jumps to a negative address or sets a negative relative base
+    InvalidAddress {
+        /// Address of the failing instruction
+        ip: Address,
+        /// Invalid address
+        address: String,
+    },
+    /// Result of an arithmetic instruction doesn't fit into a word
+    Overflow {
+        /// Address of the failing instruction
+        ip: Address,
+    },
+    /// Instruction of an extension failed
+    Extension {
+        /// Address of the failing instruction
+        ip: Address,
+        /// Description of the failure
+        message: String,
+    },
+    /// Input stream failed to provide an input value
+    Input {
+        /// Address of the input instruction
+        ip: Address,
+        /// Error of the input stream
+        source: Box<dyn core::error::Error + Send + Sync>,
+    },
+    /// Output value can't be delivered because no output stream is set or its receiver was dropped
+    Output {
+        /// Address of the output instruction
+        ip: Address,
+    },
+    /// Input or output of a replayed session doesn't match the recorded session
+    ReplayMismatch {
+        /// Address of the failing instruction
+        ip: Address,
+        /// Description of the recorded event
+        expected: String,
+        /// Description of the actual event
+        found: String,
+    },
+    /// Execution was interrupted by a cancellation token
+    Cancelled,
+    /// Execution was interrupted because the deadline of a cancellation token passed
+    TimedOut,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidInstruction { ip, instruction } => {
+                write!(f, "Invalid instruction {} at address {}", instruction, ip)
+            }
+            Error::UnknownOpcode { ip, opcode } => {
+                write!(f, "Unknown opcode {} at address {}", opcode, ip)
+            }
+            Error::InvalidAddress { ip, address } => {
+                write!(
+                    f,
+                    "Invalid address {} in instruction at address {}",
+                    address, ip
+                )
+            }
+            Error::Overflow { ip } => {
+                write!(f, "Arithmetic overflow in instruction at address {}", ip)
+            }
+            Error::Extension { ip, message } => {
+                write!(
+                    f,
+                    "Extension instruction at address {} failed: {}",
+                    ip, message
+                )
+            }
+            Error::Input { ip, source } => {
+                write!(
+                    f,
+                    "Input for instruction at address {} failed: {}",
+                    ip, source
+                )
+            }
+            Error::Output { ip } => {
+                write!(
+                    f,
+                    "Output of instruction at address {} failed: output closed",
+                    ip
+                )
+            }
+            Error::ReplayMismatch {
+                ip,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Replay mismatch at address {}: expected {}, found {}",
+                ip, expected, found
+            ),
+            Error::Cancelled => write!(f, "Execution cancelled"),
+            Error::TimedOut => write!(f, "Execution timed out"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Input { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        std::io::Error::other(err)
+    }
+}
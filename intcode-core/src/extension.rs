@@ -0,0 +1,132 @@
+//! Advent of Code 2019: Intcode instruction set extensions
+
+use crate::error::Error;
+use crate::instruction::Param;
+use crate::memory::{Address, Memory};
+use crate::word::Word;
+use alloc::string::{String, ToString};
+
+/// Intcode instruction set extension
+///
+/// Extensions add custom instructions to a virtual machine (see `Vm::extension`). Parameters of
+/// custom instructions are decoded like those of builtin instructions (including parameter
+/// modes), so the extension only needs to declare how many parameters it uses. Extensions must
+/// be `Send`, so that virtual machines can be moved between threads.
+pub trait Extension<V: Word>: Send {
+    /// Number of parameters the instruction uses
+    fn params(&self) -> usize;
+
+    /// Execute instruction
+    ///
+    /// Unless the instruction jumps (see `Context::jump`), the instruction pointer is advanced to
+    /// the next instruction afterwards. If an error is returned, the instruction pointer stays at
+    /// the failed instruction.
+    fn execute(&self, ctx: &mut Context<'_, V>) -> Result<(), Error>;
+}
+
+/// Execution context of a custom instruction
+#[derive(Debug)]
+pub struct Context<'a, V: Word> {
+    /// Memory of the virtual machine
+    memory: &'a mut Memory<V>,
+    /// Decoded parameters of the instruction
+    params: &'a [Param<V>],
+    /// Address of the instruction
+    ip: Address,
+    /// Relative base of the virtual machine
+    relative_base: Address,
+    /// Address to jump to after executing the instruction
+    jump: Option<Address>,
+}
+
+impl<'a, V: Word> Context<'a, V> {
+    /// Create new context for executing the instruction at the given address
+    pub(crate) fn new(
+        memory: &'a mut Memory<V>,
+        params: &'a [Param<V>],
+        ip: Address,
+        relative_base: Address,
+    ) -> Self {
+        Self {
+            memory,
+            params,
+            ip,
+            relative_base,
+            jump: None,
+        }
+    }
+
+    /// Return the address to jump to, if the instruction jumps
+    pub(crate) fn jump_target(&self) -> Option<Address> {
+        self.jump
+    }
+
+    /// Return the address of the instruction
+    pub fn ip(&self) -> Address {
+        self.ip
+    }
+
+    /// Return the relative base
+    pub fn relative_base(&self) -> Address {
+        self.relative_base
+    }
+
+    /// Return the parameters of the instruction
+    pub fn params(&self) -> &[Param<V>] {
+        self.params
+    }
+
+    /// Fetch value of the parameter with the given number
+    ///
+    /// Panics if the instruction has no parameter with the given number (see `Extension::params`).
+    pub fn fetch(&self, n: usize) -> V {
+        self.params[n].fetch(self.memory, self.relative_base)
+    }
+
+    /// Fetch value of the parameter with the given number as an address. Fails with
+    /// `Error::InvalidAddress` if the value isn't a valid address.
+    pub fn fetch_address(&self, n: usize) -> Result<Address, Error> {
+        self.params[n]
+            .fetch_address(self.memory, self.relative_base)
+            .ok_or_else(|| Error::InvalidAddress {
+                ip: self.ip,
+                address: self.fetch(n).to_string(),
+            })
+    }
+
+    /// Store value into the parameter with the given number. Fails with
+    /// `Error::InvalidInstruction` if the parameter is an immediate mode parameter.
+    pub fn store(&mut self, n: usize, value: V) -> Result<(), Error> {
+        if let Param::Immediate(_) = self.params[n] {
+            return Err(Error::InvalidInstruction {
+                ip: self.ip,
+                instruction: self.memory.get(self.ip).to_string(),
+            });
+        }
+        self.params[n].store(self.memory, self.relative_base, value);
+        Ok(())
+    }
+
+    /// Set instruction pointer to the given address after executing the instruction
+    pub fn jump(&mut self, addr: Address) {
+        self.jump = Some(addr);
+    }
+
+    /// Return a reference to the memory
+    pub fn memory(&self) -> &Memory<V> {
+        self.memory
+    }
+
+    /// Return a mutable reference to the memory
+    pub fn memory_mut(&mut self) -> &mut Memory<V> {
+        self.memory
+    }
+
+    /// Create an error for failing the instruction with the given message
+    pub fn error(&self, message: impl Into<String>) -> Error {
+        Error::Extension {
+            ip: self.ip,
+            message: message.into(),
+        }
+    }
+}
@@ -0,0 +1,446 @@
+//! Advent of Code 2019: Intcode instructions
+
+use crate::error::Error;
+use crate::memory::{Address, Memory};
+use crate::word::Word;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+/// Intcode parameter
+///
+/// Instructions in Intcode use a certain number of parameters in certain parameter modes. The
+/// mode of a parameter determines how the parameter is used to fetch or store the actual value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Param<V> {
+    /// Position mode: parameter points to an address containing the value
+    Position(Address),
+    /// Immediate mode: parameter is used as the value
+    Immediate(V),
+    /// Relative mode: parameter is an offset to the relative base, pointing to an address
+    /// containing the value
+    Relative(isize),
+}
+
+impl<V: Word> fmt::Display for Param<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Param::Position(addr) => write!(f, "[{}]", addr),
+            Param::Immediate(value) => write!(f, "{}", value),
+            Param::Relative(offset) => write!(f, "[rb{:+}]", offset),
+        }
+    }
+}
+
+impl<V: Word> Param<V> {
+    /// Decode parameter with the given number of the instruction at the given address
+    ///
+    /// Fails if the parameter lies past the end of memory, or if a position mode parameter
+    /// doesn't point to a valid memory address.
+    fn decode(memory: &Memory<V>, addr: Address, n: usize) -> Result<Self, Error> {
+        let instruction = memory.get(addr);
+        let invalid = || Error::InvalidInstruction {
+            ip: addr,
+            instruction: instruction.to_string(),
+        };
+        if addr + 1 + n >= memory.size() {
+            return Err(invalid());
+        }
+        let operand = memory.get(addr + 1 + n);
+        let mode = instruction
+            .to_i64()
+            .and_then(|i| Some(i / 100 / (10_i64).checked_pow(n as u32)? % 10));
+        match mode {
+            Some(0) => operand
+                .to_address()
+                .filter(|&address| address < memory.size())
+                .map(Param::Position)
+                .ok_or_else(invalid),
+            Some(1) => Ok(Param::Immediate(operand)),
+            Some(2) => operand
+                .to_i64()
+                .and_then(|offset| isize::try_from(offset).ok())
+                .map(Param::Relative)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Returns the parameter mode digit
+    pub fn mode(&self) -> u8 {
+        match self {
+            Param::Position(_) => 0,
+            Param::Immediate(_) => 1,
+            Param::Relative(_) => 2,
+        }
+    }
+
+    /// Encode parameter into the word that follows the instruction word
+    pub fn encode(&self) -> V {
+        match self {
+            Param::Position(addr) => i64::try_from(*addr)
+                .ok()
+                .and_then(V::from_i64)
+                .unwrap_or_else(|| panic!("Address {} doesn't fit into a word", addr)),
+            Param::Immediate(value) => value.clone(),
+            Param::Relative(offset) => i64::try_from(*offset)
+                .ok()
+                .and_then(V::from_i64)
+                .unwrap_or_else(|| panic!("Offset {} doesn't fit into a word", offset)),
+        }
+    }
+
+    /// Returns the address this parameter points to (using the given relative base), or `None`
+    /// for immediate mode parameters and relative mode parameters pointing below address zero
+    pub fn address_with(&self, relative_base: Address) -> Option<Address> {
+        match self {
+            Param::Position(address) => Some(*address),
+            Param::Immediate(_) => None,
+            Param::Relative(offset) => relative_base.checked_add_signed(*offset),
+        }
+    }
+
+    /// Fetch value for this parameter
+    ///
+    /// Panics if the parameter doesn't point into memory (instructions decoded by
+    /// `Machine::decode` are checked, so their parameters always do).
+    pub fn fetch(&self, memory: &Memory<V>, relative_base: Address) -> V {
+        match self {
+            Param::Immediate(value) => value.clone(),
+            Param::Position(_) | Param::Relative(_) => {
+                let address = self.address_with(relative_base);
+                memory.get(address.expect("Parameter points below address zero"))
+            }
+        }
+    }
+
+    /// Fetch address for this parameter (value used as an address, e.g. as jump target), or
+    /// `None` if the value is negative or too large
+    pub fn fetch_address(&self, memory: &Memory<V>, relative_base: Address) -> Option<Address> {
+        self.fetch(memory, relative_base).to_address()
+    }
+
+    /// Store value into this parameter
+    ///
+    /// Panics if the parameter is an immediate mode parameter or doesn't point into memory (see
+    /// `fetch`).
+    pub fn store(&self, memory: &mut Memory<V>, relative_base: Address, value: V) {
+        match self.address_with(relative_base) {
+            Some(address) => memory.set(address, value),
+            None => panic!("Can't store to immediate mode parameter"),
+        }
+    }
+}
+
+/// Intcode instruction
+///
+/// Instructions in Intcode consist of the opcode that determines the operation and zero or more
+/// parameters depending on which opcode is used. In memory, an instruction is encoded as a word
+/// containing the opcode (two lowest decimal digits) and the parameter modes (one decimal digit
+/// per parameter), followed by one word per parameter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Instruction<V> {
+    /// Addition. Adds p1 and p2 and stores the sum in p3
+    Add(Param<V>, Param<V>, Param<V>),
+    /// Addition. Multiplies p1 and p2 and stores the product in p3
+    Multiply(Param<V>, Param<V>, Param<V>),
+    /// Get value from input and store it in p1
+    Input(Param<V>),
+    /// Output value
+    Output(Param<V>),
+    /// Jump if true / not zero: set instruction pointer to p2 if p1 is not zero
+    JumpIfNotZero(Param<V>, Param<V>),
+    /// Jump if false / zero: set instruction pointer to p2 if p1 is zero
+    JumpIfZero(Param<V>, Param<V>),
+    /// Less than: if p1 is less than p2, stores 1 to p3, 0 otherwise
+    LessThan(Param<V>, Param<V>, Param<V>),
+    /// Equals: if p1 equals p2, stores 1 to p3, 0 otherwise
+    Equals(Param<V>, Param<V>, Param<V>),
+    /// Adjust relative base: adds p1 to the relative base
+    AdjustRelativeBase(Param<V>),
+    /// Program done
+    Done,
+    /// Instruction of an extension with the given opcode
+    Extension(u8, Vec<Param<V>>),
+}
+
+impl<V: Word> fmt::Display for Instruction<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Add(p1, p2, p3) => write!(f, "add {} {} {}", p1, p2, p3),
+            Instruction::Multiply(p1, p2, p3) => write!(f, "mul {} {} {}", p1, p2, p3),
+            Instruction::Input(p1) => write!(f, "in {}", p1),
+            Instruction::Output(p1) => write!(f, "out {}", p1),
+            Instruction::JumpIfNotZero(p1, p2) => write!(f, "jnz {} {}", p1, p2),
+            Instruction::JumpIfZero(p1, p2) => write!(f, "jz  {} {}", p1, p2),
+            Instruction::LessThan(p1, p2, p3) => write!(f, "lt  {} {} {}", p1, p2, p3),
+            Instruction::Equals(p1, p2, p3) => write!(f, "eq  {} {} {}", p1, p2, p3),
+            Instruction::AdjustRelativeBase(p1) => write!(f, "arb {}", p1),
+            Instruction::Done => write!(f, "done"),
+            Instruction::Extension(opcode, params) => {
+                write!(f, "op{}", opcode)?;
+                for param in params {
+                    write!(f, " {}", param)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<V: Word> Instruction<V> {
+    /// Opcodes of builtin instructions
+    pub const BUILTIN_OPCODES: [u8; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+    /// Decode (builtin) instruction at the given memory address
+    pub fn decode(memory: &Memory<V>, addr: Address) -> Result<Self, Error> {
+        Self::decode_with(memory, addr, |_opcode| None)
+    }
+
+    /// Decode instruction at the given memory address. Non-builtin opcodes are decoded as
+    /// extension instructions if the given function returns their number of parameters.
+    ///
+    /// Fails if an operand is invalid (e.g. a position past the end of memory) or a builtin
+    /// instruction would store its result into an immediate mode parameter.
+    pub fn decode_with(
+        memory: &Memory<V>,
+        addr: Address,
+        extension_params: impl Fn(u8) -> Option<usize>,
+    ) -> Result<Self, Error> {
+        if addr >= memory.size() {
+            return Err(Error::InvalidInstruction {
+                ip: addr,
+                instruction: "<end of memory>".to_string(),
+            });
+        }
+        let instruction = memory.get(addr);
+        let opcode = instruction
+            .to_i64()
+            .ok_or_else(|| Error::InvalidInstruction {
+                ip: addr,
+                instruction: instruction.to_string(),
+            })?
+            % 100;
+        let param = |n| Param::decode(memory, addr, n);
+        let decoded = match opcode {
+            1 => Instruction::Add(param(0)?, param(1)?, param(2)?),
+            2 => Instruction::Multiply(param(0)?, param(1)?, param(2)?),
+            3 => Instruction::Input(param(0)?),
+            4 => Instruction::Output(param(0)?),
+            5 => Instruction::JumpIfNotZero(param(0)?, param(1)?),
+            6 => Instruction::JumpIfZero(param(0)?, param(1)?),
+            7 => Instruction::LessThan(param(0)?, param(1)?, param(2)?),
+            8 => Instruction::Equals(param(0)?, param(1)?, param(2)?),
+            9 => Instruction::AdjustRelativeBase(param(0)?),
+            99 => Instruction::Done,
+            _ => match u8::try_from(opcode)
+                .ok()
+                .and_then(|opcode| Some((opcode, extension_params(opcode)?)))
+            {
+                Some((opcode, params)) => Instruction::Extension(
+                    opcode,
+                    (0..params).map(param).collect::<Result<_, _>>()?,
+                ),
+                None => return Err(Error::UnknownOpcode { ip: addr, opcode }),
+            },
+        };
+        if let Some(Param::Immediate(_)) = decoded.target() {
+            return Err(Error::InvalidInstruction {
+                ip: addr,
+                instruction: instruction.to_string(),
+            });
+        }
+        Ok(decoded)
+    }
+
+    /// Encode instruction into words (instruction word followed by parameter words)
+    pub fn encode(&self) -> Vec<V> {
+        let mut instruction = i64::from(self.opcode());
+        let mut words = vec![];
+        for (n, param) in self.params().enumerate() {
+            instruction += i64::from(param.mode()) * 100 * (10_i64).pow(n as u32);
+            words.push(param.encode());
+        }
+        words.insert(
+            0,
+            V::from_i64(instruction).expect("Instruction doesn't fit into a word"),
+        );
+        words
+    }
+
+    /// Returns the opcode of the instruction
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Instruction::Add(..) => 1,
+            Instruction::Multiply(..) => 2,
+            Instruction::Input(..) => 3,
+            Instruction::Output(..) => 4,
+            Instruction::JumpIfNotZero(..) => 5,
+            Instruction::JumpIfZero(..) => 6,
+            Instruction::LessThan(..) => 7,
+            Instruction::Equals(..) => 8,
+            Instruction::AdjustRelativeBase(..) => 9,
+            Instruction::Done => 99,
+            Instruction::Extension(opcode, _) => *opcode,
+        }
+    }
+
+    /// Returns the parameter with the given number
+    pub fn param(&self, n: usize) -> Option<&Param<V>> {
+        match (self, n) {
+            (Instruction::Add(p1, _, _), 0)
+            | (Instruction::Multiply(p1, _, _), 0)
+            | (Instruction::Input(p1), 0)
+            | (Instruction::Output(p1), 0)
+            | (Instruction::JumpIfNotZero(p1, _), 0)
+            | (Instruction::JumpIfZero(p1, _), 0)
+            | (Instruction::LessThan(p1, _, _), 0)
+            | (Instruction::Equals(p1, _, _), 0)
+            | (Instruction::AdjustRelativeBase(p1), 0) => Some(p1),
+            (Instruction::Add(_, p2, _), 1)
+            | (Instruction::Multiply(_, p2, _), 1)
+            | (Instruction::JumpIfNotZero(_, p2), 1)
+            | (Instruction::JumpIfZero(_, p2), 1)
+            | (Instruction::LessThan(_, p2, _), 1)
+            | (Instruction::Equals(_, p2, _), 1) => Some(p2),
+            (Instruction::Add(_, _, p3), 2)
+            | (Instruction::Multiply(_, _, p3), 2)
+            | (Instruction::LessThan(_, _, p3), 2)
+            | (Instruction::Equals(_, _, p3), 2) => Some(p3),
+            (Instruction::Extension(_, params), n) => params.get(n),
+            _ => None,
+        }
+    }
+
+    /// Returns an iterator over the parameters of the instruction
+    pub fn params(&self) -> impl Iterator<Item = &Param<V>> {
+        (0..).map_while(move |n| self.param(n))
+    }
+
+    /// Returns the parameter a builtin instruction stores its result into (if any)
+    pub fn target(&self) -> Option<&Param<V>> {
+        match self {
+            Instruction::Add(_, _, p3)
+            | Instruction::Multiply(_, _, p3)
+            | Instruction::LessThan(_, _, p3)
+            | Instruction::Equals(_, _, p3) => Some(p3),
+            Instruction::Input(p1) => Some(p1),
+            _ => None,
+        }
+    }
+
+    /// Returns the length of the instruction in words
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        1 + self.params().count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Value;
+
+    #[test]
+    fn decode() {
+        let memory = Memory::from(vec![1002, 4, 3, 4, 33, 104, -7, 99]);
+        let instruction = Instruction::decode(&memory, 0).unwrap();
+        assert_eq!(
+            instruction,
+            Instruction::Multiply(Param::Position(4), Param::Immediate(3), Param::Position(4))
+        );
+        assert_eq!(instruction.to_string(), "mul [4] 3 [4]");
+        assert_eq!(instruction.len(), 4);
+        assert_eq!(instruction.param(1), Some(&Param::Immediate(3)));
+        assert_eq!(instruction.param(3), None);
+
+        let instruction = Instruction::decode(&memory, 5).unwrap();
+        assert_eq!(instruction, Instruction::Output(Param::Immediate(-7)));
+        assert_eq!(instruction.len(), 2);
+
+        let instruction = Instruction::decode(&memory, 7).unwrap();
+        assert_eq!(instruction, Instruction::Done);
+        assert_eq!(instruction.len(), 1);
+
+        assert!(matches!(
+            Instruction::decode(&memory, 4),
+            Err(Error::UnknownOpcode { ip: 4, opcode: 33 })
+        ));
+        let extension_params = |op| if op == 33 { Some(1) } else { None };
+        assert_eq!(
+            Instruction::decode_with(&Memory::from(vec![33, 2, 99]), 0, extension_params).unwrap(),
+            Instruction::Extension(33, vec![Param::Position(2)])
+        );
+        // Position 104 is past the end of memory
+        assert!(matches!(
+            Instruction::decode_with(&memory, 4, extension_params),
+            Err(Error::InvalidInstruction { ip: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn decode_invalid_operands() {
+        // Negative position
+        let memory = Memory::from(vec![1, -5, 0, 0]);
+        assert!(matches!(
+            Instruction::decode(&memory, 0),
+            Err(Error::InvalidInstruction { ip: 0, .. })
+        ));
+        // Position past the end of memory
+        let memory = Memory::from(vec![1, 0, 4, 0]);
+        assert!(matches!(
+            Instruction::decode(&memory, 0),
+            Err(Error::InvalidInstruction { ip: 0, .. })
+        ));
+        // Truncated instruction
+        let memory = Memory::from(vec![1, 0]);
+        assert!(matches!(
+            Instruction::decode(&memory, 0),
+            Err(Error::InvalidInstruction { ip: 0, .. })
+        ));
+        // Instruction past the end of memory
+        assert!(matches!(
+            Instruction::decode(&memory, 2),
+            Err(Error::InvalidInstruction { ip: 2, .. })
+        ));
+        // Immediate mode write targets
+        let memory = Memory::from(vec![11101, 1, 1, 3, 103, 0, 99]);
+        assert!(matches!(
+            Instruction::decode(&memory, 0),
+            Err(Error::InvalidInstruction { ip: 0, ref instruction }) if instruction == "11101"
+        ));
+        assert!(matches!(
+            Instruction::decode(&memory, 4),
+            Err(Error::InvalidInstruction { ip: 4, ref instruction }) if instruction == "103"
+        ));
+    }
+
+    #[test]
+    fn encode() {
+        let program: Vec<Value> = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        let memory = Memory::from(program.clone());
+        let mut addr = 0;
+        let mut words = Vec::new();
+        for _ in 0..6 {
+            let instruction = Instruction::decode(&memory, addr).unwrap();
+            words.extend(instruction.encode());
+            addr += instruction.len();
+        }
+        assert_eq!(addr, 19);
+        assert_eq!(words, &program[..19]);
+
+        let instruction = Instruction::<Value>::Extension(
+            42,
+            vec![Param::Immediate(1), Param::Position(2), Param::Immediate(3)],
+        );
+        assert_eq!(instruction.encode(), &[10142, 1, 2, 3]);
+    }
+}
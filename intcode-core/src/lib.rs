@@ -0,0 +1,34 @@
+//! Advent of Code 2019: Intcode machine core
+//!
+//! Memory, instruction set and instruction semantics of the Intcode machine. This crate only
+//! depends on `core` and `alloc` (`no_std`), so the interpreter can be used in constrained
+//! environments and deterministic test harnesses without an async runtime. With the `std`
+//! feature, errors implement `std::error::Error`.
+
+#![no_std]
+#![warn(missing_docs, missing_debug_implementations, rust_2018_idioms)]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+mod binary;
+pub use self::binary::BinaryError;
+
+mod error;
+pub use self::error::Error;
+
+mod extension;
+pub use self::extension::{Context, Extension};
+
+mod instruction;
+pub use self::instruction::{Instruction, Param};
+
+mod machine;
+pub use self::machine::{Effect, Machine};
+
+mod memory;
+pub use self::memory::{Address, Memory, ParseMemoryError, Value};
+
+mod word;
+pub use self::word::{Arithmetic, Word};
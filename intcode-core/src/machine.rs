@@ -0,0 +1,367 @@
+//! Advent of Code 2019: Intcode machine
+
+use crate::error::Error;
+use crate::extension::{Context, Extension};
+use crate::instruction::{Instruction, Param};
+use crate::memory::{Address, Memory, Value};
+use crate::word::{Arithmetic, Word};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+/// Effect of executing an instruction on the environment of the machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect<V = Value> {
+    /// Instruction was executed without interacting with the environment
+    None,
+    /// Input instruction needs an input value (and wasn't executed yet)
+    Input,
+    /// Output instruction produced the given value
+    Output(V),
+    /// Program halted
+    Halt,
+}
+
+/// Intcode machine
+///
+/// A machine consists of memory, registers and the instruction set (builtin instructions and
+/// registered extensions), but has no notion of where input values come from or where output
+/// values go. It implements the instruction semantics without depending on an async runtime (or
+/// even `std`), so it can be used in constrained environments and deterministic test harnesses.
+/// The async virtual machine (`Vm`) is built on top of it.
+pub struct Machine<V: Word = Value> {
+    /// Memory of the machine
+    pub memory: Memory<V>,
+    /// Instruction pointer (address of next instruction)
+    pub ip: Address,
+    /// Relative base (base address for relative mode parameters)
+    pub relative_base: Address,
+    /// Flag to signal that the program is done
+    pub done: bool,
+    /// Arithmetic policy for handling overflows
+    pub arithmetic: Arithmetic,
+    /// Registered instruction set extensions by opcode
+    extensions: BTreeMap<u8, Box<dyn Extension<V>>>,
+}
+
+impl<V: Word> fmt::Debug for Machine<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Machine")
+            .field("memory", &self.memory)
+            .field("ip", &self.ip)
+            .field("relative-base", &self.relative_base)
+            .field("done", &self.done)
+            .field("arithmetic", &self.arithmetic)
+            .field("extensions", &self.extensions.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<V: Word> From<Memory<V>> for Machine<V> {
+    fn from(memory: Memory<V>) -> Self {
+        Self {
+            memory,
+            ip: Address::default(),
+            relative_base: Address::default(),
+            done: false,
+            arithmetic: Arithmetic::default(),
+            extensions: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V: Word> Machine<V> {
+    /// Create new machine with the given program memory
+    pub fn new(program: Memory<V>) -> Self {
+        Self::from(program)
+    }
+
+    /// Register an instruction set extension for the given opcode
+    ///
+    /// Panics if the opcode is not a two digit number or is used by a builtin instruction.
+    pub fn extension(&mut self, opcode: u8, extension: impl Extension<V> + 'static) -> &mut Self {
+        assert!(
+            opcode < 100 && !Instruction::<V>::BUILTIN_OPCODES.contains(&opcode),
+            "Opcode {} is not available for extensions",
+            opcode
+        );
+        self.extensions.insert(opcode, Box::new(extension));
+        self
+    }
+
+    /// Return opcodes of all registered extensions
+    pub fn extension_opcodes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.extensions.keys().copied()
+    }
+
+    /// Decode the next instruction (including instructions of registered extensions)
+    ///
+    /// Besides failing for invalid instructions, decoding fails with `Error::InvalidAddress` if a
+    /// relative mode parameter doesn't point into memory (using the current relative base), so
+    /// all parameters of a decoded instruction can be accessed.
+    pub fn decode(&self) -> Result<Instruction<V>, Error> {
+        let instruction = Instruction::decode_with(&self.memory, self.ip, |opcode| {
+            self.extensions
+                .get(&opcode)
+                .map(|extension| extension.params())
+        })?;
+        for param in instruction.params() {
+            if let Param::Relative(offset) = param {
+                match param.address_with(self.relative_base) {
+                    Some(address) if address < self.memory.size() => (),
+                    _ => {
+                        let address = self.relative_base as i128 + *offset as i128;
+                        return Err(self.invalid_address(address));
+                    }
+                }
+            }
+        }
+        Ok(instruction)
+    }
+
+    /// Create an error for accessing the given invalid address in the current instruction
+    fn invalid_address(&self, address: impl fmt::Display) -> Error {
+        Error::InvalidAddress {
+            ip: self.ip,
+            address: address.to_string(),
+        }
+    }
+
+    /// Fetch the jump target of the given parameter
+    fn jump_target(&self, param: &Param<V>) -> Result<Address, Error> {
+        let target = param.fetch(&self.memory, self.relative_base);
+        target
+            .to_address()
+            .ok_or_else(|| self.invalid_address(target))
+    }
+
+    /// Execute the given instruction (which must be the next instruction)
+    ///
+    /// Input instructions aren't executed, but return `Effect::Input` so that the caller can
+    /// obtain an input value and finish the instruction with `finish_input`. Output instructions
+    /// return the output value and need to be finished with `finish_output` once the value was
+    /// delivered. All other instructions are executed completely. If an error is returned (e.g.
+    /// for a negative jump target or relative base), the machine stays at the failed instruction.
+    pub fn execute(&mut self, instruction: &Instruction<V>) -> Result<Effect<V>, Error> {
+        let rb = self.relative_base;
+        match instruction {
+            Instruction::Add(p1, p2, p3) => {
+                let result = self
+                    .arithmetic
+                    .add(&p1.fetch(&self.memory, rb), &p2.fetch(&self.memory, rb))
+                    .ok_or(Error::Overflow { ip: self.ip })?;
+                p3.store(&mut self.memory, rb, result);
+                self.ip += 4;
+            }
+            Instruction::Multiply(p1, p2, p3) => {
+                let result = self
+                    .arithmetic
+                    .mul(&p1.fetch(&self.memory, rb), &p2.fetch(&self.memory, rb))
+                    .ok_or(Error::Overflow { ip: self.ip })?;
+                p3.store(&mut self.memory, rb, result);
+                self.ip += 4;
+            }
+            Instruction::Input(_) => return Ok(Effect::Input),
+            Instruction::Output(p1) => return Ok(Effect::Output(p1.fetch(&self.memory, rb))),
+            Instruction::JumpIfNotZero(p1, p2) => {
+                if p1.fetch(&self.memory, rb) != V::from(0) {
+                    self.ip = self.jump_target(p2)?;
+                } else {
+                    self.ip += 3;
+                }
+            }
+            Instruction::JumpIfZero(p1, p2) => {
+                if p1.fetch(&self.memory, rb) == V::from(0) {
+                    self.ip = self.jump_target(p2)?;
+                } else {
+                    self.ip += 3;
+                }
+            }
+            Instruction::LessThan(p1, p2, p3) => {
+                let less = p1.fetch(&self.memory, rb) < p2.fetch(&self.memory, rb);
+                p3.store(&mut self.memory, rb, V::from(u8::from(less)));
+                self.ip += 4;
+            }
+            Instruction::Equals(p1, p2, p3) => {
+                let equal = p1.fetch(&self.memory, rb) == p2.fetch(&self.memory, rb);
+                p3.store(&mut self.memory, rb, V::from(u8::from(equal)));
+                self.ip += 4;
+            }
+            Instruction::AdjustRelativeBase(p1) => {
+                let offset = p1.fetch(&self.memory, rb);
+                self.relative_base = match offset.to_i64() {
+                    Some(offset) => isize::try_from(offset)
+                        .ok()
+                        .and_then(|offset| rb.checked_add_signed(offset))
+                        .ok_or_else(|| self.invalid_address(rb as i128 + i128::from(offset)))?,
+                    None => return Err(self.invalid_address(format_args!("{}+{}", rb, offset))),
+                };
+                self.ip += 2;
+            }
+            Instruction::Done => {
+                self.done = true;
+                return Ok(Effect::Halt);
+            }
+            Instruction::Extension(opcode, params) => {
+                let extension = self.extensions.get(opcode).ok_or(Error::UnknownOpcode {
+                    ip: self.ip,
+                    opcode: i64::from(*opcode),
+                })?;
+                let mut ctx = Context::new(&mut self.memory, params, self.ip, rb);
+                extension.execute(&mut ctx)?;
+                self.ip = ctx.jump_target().unwrap_or(self.ip + 1 + params.len());
+            }
+        }
+        Ok(Effect::None)
+    }
+
+    /// Finish the given input instruction by storing the given input value
+    pub fn finish_input(&mut self, instruction: &Instruction<V>, value: V) {
+        match instruction {
+            Instruction::Input(p1) => {
+                p1.store(&mut self.memory, self.relative_base, value);
+                self.ip += 2;
+            }
+            _ => panic!("Can't finish {} as input instruction", instruction),
+        }
+    }
+
+    /// Finish the given output instruction after delivering its output value
+    pub fn finish_output(&mut self, instruction: &Instruction<V>) {
+        match instruction {
+            Instruction::Output(_) => self.ip += 2,
+            _ => panic!("Can't finish {} as output instruction", instruction),
+        }
+    }
+
+    /// Run one step, taking an input value from the given iterator if needed
+    ///
+    /// Returns `Effect::Input` without executing anything if an input value is needed but the
+    /// iterator doesn't yield one.
+    pub fn step(&mut self, input: &mut impl Iterator<Item = V>) -> Result<Effect<V>, Error> {
+        let instruction = self.decode()?;
+        let effect = self.execute(&instruction)?;
+        match &effect {
+            Effect::Input => {
+                if let Some(value) = input.next() {
+                    self.finish_input(&instruction, value);
+                    return Ok(Effect::None);
+                }
+            }
+            Effect::Output(_) => self.finish_output(&instruction),
+            Effect::None | Effect::Halt => (),
+        }
+        Ok(effect)
+    }
+
+    /// Run program with the given input values until it is done or needs more input, and return
+    /// all output values
+    pub fn run(&mut self, input: impl IntoIterator<Item = V>) -> Result<Vec<V>, Error> {
+        let mut input = input.into_iter();
+        let mut output = Vec::new();
+        while !self.done {
+            match self.step(&mut input)? {
+                Effect::Input => break,
+                Effect::Output(value) => output.push(value),
+                Effect::None | Effect::Halt => (),
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn run() {
+        // Output the sum of every two input values
+        let program = Memory::from(vec![3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0]);
+        let mut machine = Machine::new(program);
+        assert_eq!(machine.run(vec![1]).unwrap(), &[] as &[Value]);
+        assert_eq!(machine.ip, 2);
+        assert!(!machine.done);
+        assert_eq!(machine.run(vec![2]).unwrap(), &[3]);
+        assert!(machine.done);
+        assert_eq!(machine.step(&mut None.into_iter()).unwrap(), Effect::Halt);
+    }
+
+    #[test]
+    fn invalid_addresses() {
+        let error = |program: Vec<Value>| {
+            let mut machine = Machine::new(Memory::from(program));
+            match machine.run(vec![]) {
+                Err(Error::InvalidAddress { ip, address }) => (ip, address),
+                result => panic!("Unexpected result {:?}", result),
+            }
+        };
+        // Negative relative base
+        assert_eq!(error(vec![109, -1, 99]), (0, "-1".into()));
+        // Relative read and write past the end of memory
+        assert_eq!(error(vec![204, 100, 99]), (0, "100".into()));
+        assert_eq!(error(vec![109, 2, 21101, 1, 1, 100, 99]), (2, "102".into()));
+        // Negative jump target
+        assert_eq!(error(vec![1105, 1, -1]), (0, "-1".into()));
+    }
+
+    /// Test extension that stores its first parameter into its second parameter
+    struct Store;
+
+    impl Extension<Value> for Store {
+        fn params(&self) -> usize {
+            2
+        }
+
+        fn execute(&self, ctx: &mut Context<'_, Value>) -> Result<(), Error> {
+            ctx.store(1, ctx.fetch(0))
+        }
+    }
+
+    #[test]
+    fn invalid_extensions() {
+        let mut machine = Machine::new(Memory::from(vec![150, 7, 0, 99]));
+        machine.extension(50, Store);
+        assert_eq!(machine.run(vec![]).unwrap(), &[] as &[Value]);
+        assert_eq!(machine.memory.get(0), 7);
+
+        // Storing into an immediate mode parameter
+        let mut machine = Machine::new(Memory::from(vec![1150, 7, 0, 99]));
+        machine.extension(50, Store);
+        assert!(matches!(
+            machine.run(vec![]),
+            Err(Error::InvalidInstruction { ip: 0, .. })
+        ));
+        assert_eq!(machine.memory.get(0), 1150);
+
+        // Executing an instruction of an extension that isn't registered
+        let instruction = Instruction::decode_with(&machine.memory, 0, |_| Some(2)).unwrap();
+        let mut machine = Machine::new(machine.memory.clone());
+        assert!(matches!(
+            machine.execute(&instruction),
+            Err(Error::UnknownOpcode { ip: 0, opcode: 50 })
+        ));
+    }
+
+    #[test]
+    fn execute() {
+        let mut machine = Machine::new(Memory::from(vec![3, 0, 104, 42, 99]));
+        let instruction = machine.decode().unwrap();
+        assert_eq!(machine.execute(&instruction).unwrap(), Effect::Input);
+        assert_eq!(machine.ip, 0);
+        machine.finish_input(&instruction, 7);
+        assert_eq!(machine.memory.get(0), 7);
+        let instruction = machine.decode().unwrap();
+        assert_eq!(machine.execute(&instruction).unwrap(), Effect::Output(42));
+        assert_eq!(machine.ip, 2);
+        machine.finish_output(&instruction);
+        let instruction = machine.decode().unwrap();
+        assert_eq!(machine.execute(&instruction).unwrap(), Effect::Halt);
+        assert!(machine.done);
+        assert_eq!(machine.ip, 4);
+    }
+}
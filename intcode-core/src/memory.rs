@@ -0,0 +1,266 @@
+//! Advent of Code 2019: Intcode memory
+
+use crate::word::Word;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// Intcode memory address
+pub type Address = usize;
+
+/// Intcode memory value (default word type)
+pub type Value = i32;
+
+/// Number of values per memory page
+const PAGE_SIZE: usize = 1024;
+
+/// Intcode memory
+///
+/// Memory of an Intcode machine is a continuous range of signed integers (words of type `V`)
+/// addressed by their position (zero based index). Memory can be loaded from (ASCII) text
+/// files with content encoded as comma separated values (see `FromStr` implementation).
+///
+/// Values are stored in reference counted pages that are shared between clones of the memory.
+/// A page is only copied when it is written to while being shared (copy-on-write), so cloning
+/// memory (e.g. to run the same program in many virtual machines) is cheap.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Memory<V = Value> {
+    /// Pages of values (all pages except the last one have `PAGE_SIZE` values)
+    pages: Vec<Arc<Vec<V>>>,
+    /// Total number of values
+    size: usize,
+}
+
+impl<V: Word> fmt::Debug for Memory<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Formats memory as comma separated values (the program text format)
+impl<V: Word> fmt::Display for Memory<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, value) in self.iter().enumerate() {
+            if addr > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<V: Word> From<Vec<V>> for Memory<V> {
+    fn from(data: Vec<V>) -> Self {
+        let size = data.len();
+        let pages = data
+            .chunks(PAGE_SIZE)
+            .map(|chunk| Arc::new(chunk.to_vec()))
+            .collect();
+        Memory { pages, size }
+    }
+}
+
+impl<V: Word, T: AsRef<[V]>> PartialEq<T> for Memory<V> {
+    fn eq(&self, other: &T) -> bool {
+        self.iter().eq(other.as_ref().iter().cloned())
+    }
+}
+
+/// Serializes memory as a sequence of words
+#[cfg(feature = "serde")]
+impl<V: Word + serde::Serialize> serde::Serialize for Memory<V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// Deserializes memory from a sequence of words
+#[cfg(feature = "serde")]
+impl<'de, V: Word + serde::Deserialize<'de>> serde::Deserialize<'de> for Memory<V> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Memory::from)
+    }
+}
+
+/// Error returned when parsing program text fails
+#[derive(Debug)]
+pub struct ParseMemoryError {
+    /// Line number (starting at 1)
+    pub line: usize,
+    /// Column number (starting at 1)
+    pub column: usize,
+    /// Offending token
+    pub token: String,
+    /// Description of the error
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseMemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} '{}' in line {}, column {}",
+            self.message, self.token, self.line, self.column
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseMemoryError {}
+
+/// Parses program text consisting of comma separated values
+///
+/// Whitespace around values is ignored, and so are comments (starting with `#` and extending to
+/// the end of the line). Values may span multiple lines, with or without a comma at the end of a
+/// line, and a trailing comma after the last value is allowed.
+impl<V: Word + FromStr> FromStr for Memory<V> {
+    type Err = ParseMemoryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut data = Vec::new();
+        // A value was parsed since the last comma
+        let mut have_value = false;
+        for (line_idx, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            // A value was parsed in this line without a comma following it
+            let mut need_comma = false;
+            let mut rest = line;
+            loop {
+                rest = rest.trim_start();
+                if rest.is_empty() {
+                    break;
+                }
+                let error = |token: &str, message| ParseMemoryError {
+                    line: line_idx + 1,
+                    column: line[..line.len() - rest.len()].chars().count() + 1,
+                    token: token.to_string(),
+                    message,
+                };
+                if let Some(tail) = rest.strip_prefix(',') {
+                    if !have_value {
+                        return Err(error(",", "Unexpected"));
+                    }
+                    have_value = false;
+                    need_comma = false;
+                    rest = tail;
+                } else {
+                    let len = rest
+                        .find(|c: char| c == ',' || c.is_whitespace())
+                        .unwrap_or(rest.len());
+                    let token = &rest[..len];
+                    if need_comma {
+                        return Err(error(token, "Missing comma before"));
+                    }
+                    data.push(token.parse().map_err(|_| error(token, "Invalid value"))?);
+                    have_value = true;
+                    need_comma = true;
+                    rest = &rest[len..];
+                }
+            }
+        }
+        Ok(Memory::from(data))
+    }
+}
+
+impl<V: Word> Memory<V> {
+    /// Returns the size of memory
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Get value at given memory address
+    pub fn get(&self, addr: Address) -> V {
+        assert!(
+            addr < self.size(),
+            "Reading from memory out of bounds ({} >= {})",
+            addr,
+            self.size()
+        );
+        self.pages[addr / PAGE_SIZE][addr % PAGE_SIZE].clone()
+    }
+
+    /// Set value at given memory address
+    pub fn set(&mut self, addr: Address, value: V) {
+        assert!(
+            addr < self.size(),
+            "Writing to memory out of bounds ({} >= {})",
+            addr,
+            self.size()
+        );
+        Arc::make_mut(&mut self.pages[addr / PAGE_SIZE])[addr % PAGE_SIZE] = value;
+    }
+
+    /// Returns an iterator over all values in memory
+    pub fn iter(&self) -> impl Iterator<Item = V> + '_ {
+        self.pages.iter().flat_map(|page| page.iter().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn pages() {
+        let memory = Memory::from((0..2500).collect::<Vec<Value>>());
+        assert_eq!(memory.size(), 2500);
+        assert_eq!(memory.pages.len(), 3);
+        assert_eq!(memory.get(0), 0);
+        assert_eq!(memory.get(1024), 1024);
+        assert_eq!(memory.get(2499), 2499);
+        assert_eq!(memory, (0..2500).collect::<Vec<Value>>());
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let memory = Memory::from((0..2500).collect::<Vec<Value>>());
+        let mut clone = memory.clone();
+        assert!(memory
+            .pages
+            .iter()
+            .zip(&clone.pages)
+            .all(|(p1, p2)| Arc::ptr_eq(p1, p2)));
+
+        clone.set(1500, -1);
+        assert_eq!(memory.get(1500), 1500);
+        assert_eq!(clone.get(1500), -1);
+        assert!(Arc::ptr_eq(&memory.pages[0], &clone.pages[0]));
+        assert!(!Arc::ptr_eq(&memory.pages[1], &clone.pages[1]));
+        assert!(Arc::ptr_eq(&memory.pages[2], &clone.pages[2]));
+        assert_ne!(memory, clone);
+    }
+
+    #[test]
+    fn parse() {
+        let memory: Memory = "1,2, 3 ,-4\n".parse().unwrap();
+        assert_eq!(memory, &[1, 2, 3, -4]);
+        let memory: Memory = "# Program\n\n  1, 2, # add\n 3,\n4\n5,\n".parse().unwrap();
+        assert_eq!(memory, &[1, 2, 3, 4, 5]);
+        let memory: Memory = "".parse().unwrap();
+        assert_eq!(memory.size(), 0);
+
+        let memory = Memory::from(vec![1002, 4, -3, 4, 33]);
+        assert_eq!(memory.to_string(), "1002,4,-3,4,33");
+        assert_eq!(memory.to_string().parse::<Memory>().unwrap(), memory);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |s: &str| s.parse::<Memory>().unwrap_err().to_string();
+        assert_eq!(error("1,2,x3"), "Invalid value 'x3' in line 1, column 5");
+        assert_eq!(error("1,\n  2,,3"), "Unexpected ',' in line 2, column 5");
+        assert_eq!(error(",1"), "Unexpected ',' in line 1, column 1");
+        assert_eq!(
+            error("1, 2 3"),
+            "Missing comma before '3' in line 1, column 6"
+        );
+        assert_eq!(
+            error("1, 99999999999"),
+            "Invalid value '99999999999' in line 1, column 4"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Advent of Code 2019: Intcode word types
+
+use crate::memory::Address;
+use core::convert::TryFrom;
+use core::fmt;
+
+/// Intcode word
+///
+/// A word is the type of values stored in memory and processed by instructions. It is
+/// implemented for `i32`, `i64` and `i128` and, with the `bigint` feature enabled, for
+/// arbitrary-precision integers (`num_bigint::BigInt`).
+pub trait Word: Clone + Ord + From<u8> + fmt::Debug + fmt::Display + Send + Sync + 'static {
+    /// Convert from a 64 bit integer, if it fits
+    fn from_i64(value: i64) -> Option<Self>;
+
+    /// Convert to a 64 bit integer, if it fits
+    fn to_i64(&self) -> Option<i64>;
+
+    /// Convert to a memory address, if it is a valid address
+    fn to_address(&self) -> Option<Address>;
+
+    /// Addition that wraps around at the boundary of the type
+    fn wrapping_add(&self, other: &Self) -> Self;
+
+    /// Multiplication that wraps around at the boundary of the type
+    fn wrapping_mul(&self, other: &Self) -> Self;
+
+    /// Addition that returns `None` on overflow
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    /// Multiplication that returns `None` on overflow
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    /// Addition that saturates at the numeric bounds of the type
+    fn saturating_add(&self, other: &Self) -> Self;
+
+    /// Multiplication that saturates at the numeric bounds of the type
+    fn saturating_mul(&self, other: &Self) -> Self;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {$(
+        impl Word for $t {
+            fn from_i64(value: i64) -> Option<Self> {
+                <$t>::try_from(value).ok()
+            }
+
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn to_address(&self) -> Option<Address> {
+                Address::try_from(*self).ok()
+            }
+
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$t>::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                <$t>::wrapping_mul(*self, *other)
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_mul(*self, *other)
+            }
+
+            fn saturating_add(&self, other: &Self) -> Self {
+                <$t>::saturating_add(*self, *other)
+            }
+
+            fn saturating_mul(&self, other: &Self) -> Self {
+                <$t>::saturating_mul(*self, *other)
+            }
+        }
+    )*};
+}
+
+impl_word!(i32, i64, i128);
+
+/// Arbitrary-precision integers never overflow, so all arithmetic policies behave the same
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn from_i64(value: i64) -> Option<Self> {
+        Some(Self::from(value))
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        num_traits::ToPrimitive::to_i64(self)
+    }
+
+    fn to_address(&self) -> Option<Address> {
+        num_traits::ToPrimitive::to_usize(self)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+/// Arithmetic policy
+///
+/// Determines how arithmetic instructions behave if the result doesn't fit into a word.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// Wrap around at the boundary of the word type
+    Wrapping,
+    /// Fail with an overflow error
+    #[default]
+    Checked,
+    /// Saturate at the numeric bounds of the word type
+    Saturating,
+}
+
+impl Arithmetic {
+    /// Add two words, returns `None` on overflow
+    pub fn add<V: Word>(self, a: &V, b: &V) -> Option<V> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_add(b)),
+            Arithmetic::Checked => a.checked_add(b),
+            Arithmetic::Saturating => Some(a.saturating_add(b)),
+        }
+    }
+
+    /// Multiply two words, returns `None` on overflow
+    pub fn mul<V: Word>(self, a: &V, b: &V) -> Option<V> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_mul(b)),
+            Arithmetic::Checked => a.checked_mul(b),
+            Arithmetic::Saturating => Some(a.saturating_mul(b)),
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use advent_of_code_2019::Input;
+use advent_of_code_2019::{init_logging, Input};
 use async_std::io;
 use futures_util::stream::TryStreamExt;
 
@@ -18,6 +18,7 @@ fn fuel_for_mass_including_fuel(mass: u32) -> u32 {
 
 #[async_std::main]
 async fn main() -> io::Result<()> {
+    init_logging();
     let mut input = Input::day(1).await?.parsed_lines();
     let mut total_fuel = 0;
     while let Some(mass) = input.try_next().await? {
@@ -1,19 +1,19 @@
 use advent_of_code_2019::intcode::Vm;
-use advent_of_code_2019::Input;
+use advent_of_code_2019::{init_logging, Input};
 use async_std::io;
 
 #[async_std::main]
 async fn main() -> io::Result<()> {
+    init_logging();
     let program = Input::day(2).await?.memory().await?;
 
-    let mut vm = Vm::new(program.clone());
-    vm.noun(12).verb(2).run().await;
+    let mut vm = Vm::new(program);
+    vm.noun(12).verb(2).run().await?;
     println!("Result: {}", vm.result());
 
     'out: for noun in 0..=99 {
         for verb in 0..=99 {
-            let mut vm = Vm::new(program.clone());
-            vm.noun(noun).verb(verb).run().await;
+            vm.reset().noun(noun).verb(verb).run().await?;
             if vm.result() == 19_690_720 {
                 println!(
                     "Noun {} verb {} produces result {}",
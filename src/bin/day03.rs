@@ -1,9 +1,8 @@
-use advent_of_code_2019::Input;
-use err_derive::Error;
+use advent_of_code_2019::{init_logging, Input};
 use futures_util::stream::TryStreamExt;
 use std::cmp::{max, min};
-use std::error;
 use std::str::FromStr;
+use std::{error, fmt};
 
 /// 2d point
 #[derive(Clone, Copy, Debug)]
@@ -98,14 +97,23 @@ struct Path {
 }
 
 /// Error returned when a path couldn't be parsed
-#[derive(Debug, Error)]
+#[derive(Debug)]
 enum ParsePathError {
-    #[error(display = "Invalid direction '{}'", _0)]
     InvalidDirection(String),
-    #[error(display = "Invalid distance '{}'", _0)]
     InvalidDistance(String),
 }
 
+impl fmt::Display for ParsePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParsePathError::InvalidDirection(dir) => write!(f, "Invalid direction '{}'", dir),
+            ParsePathError::InvalidDistance(dist) => write!(f, "Invalid distance '{}'", dist),
+        }
+    }
+}
+
+impl error::Error for ParsePathError {}
+
 impl FromStr for Path {
     type Err = ParsePathError;
 
@@ -188,6 +196,7 @@ impl Path {
 
 #[async_std::main]
 async fn main() -> Result<(), Box<dyn error::Error>> {
+    init_logging();
     let mut lines = Input::day(3).await?.lines();
     let wire1 = lines.try_next().await?.unwrap().parse::<Path>()?;
     let wire2 = lines.try_next().await?.unwrap().parse::<Path>()?;
@@ -1,4 +1,4 @@
-use advent_of_code_2019::Input;
+use advent_of_code_2019::{init_logging, Input};
 use futures_util::stream::TryStreamExt;
 use std::{error, fmt};
 
@@ -81,6 +81,7 @@ impl Password {
 
 #[async_std::main]
 async fn main() -> Result<(), Box<dyn error::Error>> {
+    init_logging();
     let mut lines = Input::day(4).await?.lines();
     let line = lines.try_next().await?.unwrap();
     let mut numbers = line.split('-');
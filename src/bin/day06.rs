@@ -1,4 +1,4 @@
-use advent_of_code_2019::Input;
+use advent_of_code_2019::{init_logging, Input};
 use async_std::io;
 use async_std::prelude::*;
 use futures_util::stream::TryStreamExt;
@@ -82,6 +82,7 @@ impl OrbitMap {
 
 #[async_std::main]
 async fn main() -> io::Result<()> {
+    init_logging();
     let lines = Input::day(6).await?.lines();
     let orbit_map = OrbitMap::load(lines).await?;
 
@@ -1,12 +1,14 @@
-use advent_of_code_2019::intcode::{Memory, Value, Vm};
-use advent_of_code_2019::Input;
+use advent_of_code_2019::intcode::{CancellationToken, Error, Memory, Report, Value, Vm};
+use advent_of_code_2019::{init_logging, Input};
 use async_std::prelude::*;
 use async_std::{io, stream};
 use futures_util::future;
-use futures_util::stream::StreamExt;
+use futures_util::stream::{StreamExt, TryStreamExt};
+use tracing::{info_span, Instrument};
 
 #[derive(Debug)]
 struct AmplifierChain {
+    phases: Vec<Value>,
     amplifiers: Vec<Vm>,
 }
 
@@ -22,7 +24,10 @@ impl AmplifierChain {
             amplifiers.push(amp);
         }
         amplifiers[0].input(stream::once(phases[0]).chain(stream::once(0)));
-        Self { amplifiers }
+        Self {
+            phases: phases.to_vec(),
+            amplifiers,
+        }
     }
 
     /// Return a stream that yields output values of the amplifier chain
@@ -30,44 +35,57 @@ impl AmplifierChain {
         self.amplifiers.last_mut().unwrap().output()
     }
 
-    /// Run the amplifier chain
-    async fn run(&mut self) {
-        future::join_all(self.amplifiers.iter_mut().map(|amp| amp.run())).await;
+    /// Run the amplifier chain and return the run reports of all amplifiers
+    async fn run(&mut self) -> Result<Vec<Report>, Error> {
+        self.run_with(&CancellationToken::new()).await
+    }
+
+    /// Run the amplifier chain (every amplifier on its own task) until done, or until the given
+    /// token is cancelled or times out
+    async fn run_with(&mut self, token: &CancellationToken) -> Result<Vec<Report>, Error> {
+        let span = info_span!("amplifier_chain", phases = ?self.phases);
+        Vm::run_all(&mut self.amplifiers, token)
+            .instrument(span)
+            .await
     }
 
     /// Run the amplifier chain and collect output into a vector
-    async fn run_and_collect(&mut self) -> Vec<Value> {
+    async fn run_and_collect(&mut self) -> Result<Vec<Value>, Error> {
         let rx = self.output();
-        self.run().join(rx.collect()).await.1
+        let (result, output) = self.run().join(rx.collect()).await;
+        result.map(|_| output)
     }
 
     /// Run the amplifier chain and collect a single result
-    async fn run_single_result(&mut self) -> Value {
-        let results = self.run_and_collect().await;
+    async fn run_single_result(&mut self) -> Result<Value, Error> {
+        let results = self.run_and_collect().await?;
         assert!(
             results.len() == 1,
             "Amplifier yielded {} results unexpectedly",
             results.len()
         );
-        results[0]
+        Ok(results[0])
     }
 
     /// Stream of amplifier chain outputs for all k-permutations of the given phase values
     fn permutate(
         program: Memory,
         phases: &[Value],
-    ) -> impl Stream<Item = (Vec<Value>, Value)> + '_ {
+    ) -> impl Stream<Item = Result<(Vec<Value>, Value), Error>> + '_ {
         stream::from_iter(permutator::KPermutationIterator::new(phases, phases.len()))
             .map(|phases| phases.into_iter().cloned().collect::<Vec<_>>())
             .map(move |phases| (Self::new(program.clone(), &phases), phases))
-            .then(|(mut amp, phases)| async move { (phases, amp.run_single_result().await) })
+            .then(|(mut amp, phases)| async move { Ok((phases, amp.run_single_result().await?)) })
     }
 
     /// Return max output over all k-permutations of the given phase values
-    async fn permutate_max(program: Memory, phases: &[Value]) -> Option<(Vec<Value>, Value)> {
+    async fn permutate_max(
+        program: Memory,
+        phases: &[Value],
+    ) -> Result<Option<(Vec<Value>, Value)>, Error> {
         Self::permutate(program, phases)
-            .fold(None, |res, (phases, thrust)| {
-                future::ready(match res {
+            .try_fold(None, |res, (phases, thrust)| {
+                future::ok(match res {
                     Some((ref _ph, ref th)) if thrust < *th => res,
                     _ => Some((phases, thrust)),
                 })
@@ -78,10 +96,11 @@ impl AmplifierChain {
 
 #[async_std::main]
 async fn main() -> io::Result<()> {
+    init_logging();
     let program = Input::day(7).await?.memory().await?;
 
     let (phases, thrust) = AmplifierChain::permutate_max(program, &[0, 1, 2, 3, 4])
-        .await
+        .await?
         .unwrap();
     println!(
         "Phase configuration {:?} yields max thruster signal of {}",
@@ -94,6 +113,7 @@ async fn main() -> io::Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[async_std::test]
     async fn part_1_example_1() {
@@ -101,7 +121,9 @@ mod tests {
             3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
         ]);
         assert_eq!(
-            AmplifierChain::permutate_max(program, &[0, 1, 2, 3, 4]).await,
+            AmplifierChain::permutate_max(program, &[0, 1, 2, 3, 4])
+                .await
+                .unwrap(),
             Some((vec![4, 3, 2, 1, 0], 43210))
         );
     }
@@ -113,7 +135,9 @@ mod tests {
             99, 0, 0,
         ]);
         assert_eq!(
-            AmplifierChain::permutate_max(program, &[0, 1, 2, 3, 4]).await,
+            AmplifierChain::permutate_max(program, &[0, 1, 2, 3, 4])
+                .await
+                .unwrap(),
             Some((vec![0, 1, 2, 3, 4], 54321))
         );
     }
@@ -125,8 +149,40 @@ mod tests {
             33, 31, 31, 1, 32, 31, 31, 4, 31, 99, 0, 0, 0,
         ]);
         assert_eq!(
-            AmplifierChain::permutate_max(program, &[0, 1, 2, 3, 4]).await,
+            AmplifierChain::permutate_max(program, &[0, 1, 2, 3, 4])
+                .await
+                .unwrap(),
             Some((vec![1, 0, 4, 3, 2], 65210))
         );
     }
+
+    #[async_std::test]
+    async fn reports() {
+        let program = Memory::from(vec![
+            3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+        ]);
+        let mut chain = AmplifierChain::new(program, &[4, 3, 2, 1, 0]);
+        let output = chain.output();
+        let (reports, output) = chain.run().join(output.collect::<Vec<_>>()).await;
+        assert_eq!(output, &[43210]);
+        let reports = reports.unwrap();
+        assert_eq!(reports.len(), 5);
+        for report in reports {
+            assert_eq!(report.steps, 6);
+            assert_eq!(report.inputs, 2);
+            assert_eq!(report.outputs, 1);
+            assert_eq!(report.ip, 14);
+            assert_eq!(report.max_address, Some(16));
+        }
+    }
+
+    #[async_std::test]
+    async fn timeout() {
+        let program = Memory::from(vec![3, 9, 3, 9, 1105, 1, 4, 99, 0, 0]);
+        let mut chain = AmplifierChain::new(program, &[0, 1]);
+        let token = CancellationToken::with_timeout(Duration::from_millis(10));
+        assert!(matches!(chain.run_with(&token).await, Err(Error::TimedOut)));
+        assert_eq!(chain.amplifiers[0].ip(), 4);
+        assert_eq!(chain.amplifiers[1].ip(), 2);
+    }
 }
@@ -0,0 +1,128 @@
+use advent_of_code_2019::init_logging;
+use advent_of_code_2019::intcode::Memory;
+use async_std::fs;
+use async_std::io;
+use std::convert::TryFrom;
+use std::env;
+
+const USAGE: &str = "Usage: intcode-convert [--text | --binary] <input> <output>";
+
+/// Memory image format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// Comma separated values
+    Text,
+    /// Compact binary format
+    Binary,
+}
+
+/// Command line arguments
+#[derive(Debug)]
+struct Args {
+    /// Format to convert to (defaults to the format the input is not in)
+    format: Option<Format>,
+    /// Path to input file
+    input: String,
+    /// Path to output file
+    output: String,
+}
+
+impl Args {
+    /// Parse command line arguments
+    fn parse(args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+        let mut format = None;
+        let mut paths = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--text" if format.is_none() => format = Some(Format::Text),
+                "--binary" if format.is_none() => format = Some(Format::Binary),
+                "-h" | "--help" | "--text" | "--binary" => return Err(usage()),
+                _ => paths.push(arg),
+            }
+        }
+        match <[String; 2]>::try_from(paths) {
+            Ok([input, output]) => Ok(Self {
+                format,
+                input,
+                output,
+            }),
+            Err(_) => Err(usage()),
+        }
+    }
+}
+
+/// Decode memory image in any format and return it with the format it was in
+fn decode(data: Vec<u8>) -> io::Result<(Memory<i64>, Format)> {
+    if Memory::<i64>::is_binary(&data) {
+        let memory = Memory::from_binary(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok((memory, Format::Binary))
+    } else {
+        let text =
+            String::from_utf8(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let memory = text
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok((memory, Format::Text))
+    }
+}
+
+/// Encode memory image in the given format
+fn encode(memory: &Memory<i64>, format: Format) -> io::Result<Vec<u8>> {
+    match format {
+        Format::Text => Ok(format!("{}\n", memory).into_bytes()),
+        Format::Binary => memory
+            .to_binary()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+#[async_std::main]
+async fn main() -> io::Result<()> {
+    init_logging();
+    let args = Args::parse(env::args().skip(1))?;
+    let (memory, input_format) = decode(fs::read(&args.input).await?)?;
+    let format = args.format.unwrap_or(match input_format {
+        Format::Text => Format::Binary,
+        Format::Binary => Format::Text,
+    });
+    fs::write(&args.output, encode(&memory, format)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> io::Result<Args> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        let args1 = args(&["in.txt", "out.icb"]).unwrap();
+        assert_eq!(args1.format, None);
+        assert_eq!(args1.input, "in.txt");
+        assert_eq!(args1.output, "out.icb");
+
+        let args2 = args(&["--text", "in.icb", "out.txt"]).unwrap();
+        assert_eq!(args2.format, Some(Format::Text));
+
+        assert!(args(&["in.txt"]).is_err());
+        assert!(args(&["in.txt", "out.icb", "x"]).is_err());
+        assert!(args(&["--text", "--binary", "in.txt", "out.icb"]).is_err());
+    }
+
+    #[test]
+    fn convert() {
+        let (memory, format) = decode(b"1,2,-3\n".to_vec()).unwrap();
+        assert_eq!(format, Format::Text);
+        let data = encode(&memory, Format::Binary).unwrap();
+        let (converted, format) = decode(data).unwrap();
+        assert_eq!(format, Format::Binary);
+        assert_eq!(converted, memory);
+        assert_eq!(encode(&converted, Format::Text).unwrap(), b"1,2,-3\n");
+        assert!(decode(b"1,x".to_vec()).is_err());
+    }
+}
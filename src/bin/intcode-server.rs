@@ -0,0 +1,316 @@
+use advent_of_code_2019::intcode::{CancellationToken, Error, Memory, Value, Vm};
+use advent_of_code_2019::{init_logging, Input};
+use async_std::channel::{self, Receiver, Sender};
+use async_std::io::{self, BufReader};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::prelude::*;
+use async_std::{stream, task};
+use std::env;
+
+const USAGE: &str = "Usage: intcode-server [--port <port>]";
+
+/// Port to listen on by default
+const DEFAULT_PORT: u16 = 2019;
+
+/// Command sent by a client (one per line)
+///
+/// - `load <values>`: run the given program (comma separated values)
+/// - `open <name>`: run a stored program (puzzle input with the given name, e.g. `day05`)
+/// - `input <values>`: send comma separated input values to the running program
+/// - `quit`: close the connection
+///
+/// Loading a program replies with `ok`, afterwards the server sends `output <value>` for every
+/// output value and `halt <steps>` when the program is done. Failures are reported as
+/// `error <message>`. Loading another program cancels the running one.
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    /// Run the given program
+    Load(Memory),
+    /// Run a stored program
+    Open(String),
+    /// Send input values
+    Input(Vec<Value>),
+    /// Close the connection
+    Quit,
+}
+
+impl Command {
+    /// Parse a command line
+    fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (command, arg) = match line.find(' ') {
+            Some(pos) => (&line[..pos], line[pos + 1..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            "load" => arg.parse().map(Command::Load).map_err(|e| e.to_string()),
+            "open" if !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                Ok(Command::Open(arg.to_string()))
+            }
+            "open" => Err(format!("Invalid program name '{}'", arg)),
+            "input" => arg
+                .split(',')
+                .map(|s| {
+                    s.trim()
+                        .parse()
+                        .map_err(|_| format!("Invalid input value '{}'", s.trim()))
+                })
+                .collect::<Result<_, _>>()
+                .map(Command::Input),
+            "quit" if arg.is_empty() => Ok(Command::Quit),
+            _ => Err(format!("Unknown command '{}'", line)),
+        }
+    }
+}
+
+/// Program running for a client
+#[derive(Debug)]
+struct Running {
+    /// Sender for input values
+    input: Sender<Value>,
+    /// Token to cancel the program
+    token: CancellationToken,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+/// Run the given program and report its output values and result to the client
+fn run(program: Memory, lines: Sender<String>) -> Running {
+    let (tx, rx) = channel::unbounded();
+    let token = CancellationToken::new();
+    let mut vm = Vm::new(program);
+    // Input never ends, so the program waits for more input until it's cancelled
+    vm.input(rx.chain(stream::pending()));
+    let mut output = vm.output();
+    let vm_token = token.clone();
+    task::spawn(async move {
+        let forward = async {
+            while let Some(value) = output.next().await {
+                let _ = lines.send(format!("output {}", value)).await;
+            }
+        };
+        let (result, ()) = vm.run_with(&vm_token).join(forward).await;
+        let line = match result {
+            Ok(report) => format!("halt {}", report.steps),
+            Err(Error::Cancelled) => return,
+            Err(err) => format!("error {}", err),
+        };
+        let _ = lines.send(line).await;
+    });
+    Running { input: tx, token }
+}
+
+/// Write lines to the client until all senders are gone
+async fn write_lines(mut stream: TcpStream, mut lines: Receiver<String>) -> io::Result<()> {
+    while let Some(line) = lines.next().await {
+        stream.write_all(format!("{}\n", line).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Handle commands of a client until the connection is closed
+async fn handle(stream: TcpStream) -> io::Result<()> {
+    let (tx, rx) = channel::unbounded();
+    let writer = task::spawn(write_lines(stream.clone(), rx));
+    let mut running = None;
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(line) = lines.next().await {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let program = match Command::parse(&line) {
+            Ok(Command::Load(program)) => Ok(program),
+            Ok(Command::Open(name)) => match Input::open(&name).await {
+                Ok(input) => input.memory().await.map_err(|e| e.to_string()),
+                Err(_) => Err(format!("Unknown program '{}'", name)),
+            },
+            Ok(Command::Input(values)) => match &running {
+                Some(Running { input, .. }) => {
+                    for value in values {
+                        input.send(value).await.ok();
+                    }
+                    continue;
+                }
+                None => Err("No program running".to_string()),
+            },
+            Ok(Command::Quit) => break,
+            Err(message) => Err(message),
+        };
+        let reply = match program {
+            Ok(program) => {
+                // Cancel previous program, so that its output doesn't mix with the new one
+                drop(running.take());
+                tx.send("ok".to_string()).await.ok();
+                running = Some(run(program, tx.clone()));
+                continue;
+            }
+            Err(message) => format!("error {}", message),
+        };
+        tx.send(reply).await.ok();
+    }
+    drop(running);
+    drop(tx);
+    writer.await
+}
+
+/// Accept clients and handle every client on its own task
+async fn serve(listener: TcpListener) -> io::Result<()> {
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let stream = stream?;
+        task::spawn(async move {
+            if let Err(err) = handle(stream).await {
+                eprintln!("Client error: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Parse command line arguments and return the port to listen on
+fn parse_args(mut args: impl Iterator<Item = String>) -> io::Result<u16> {
+    let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+    match (args.next().as_deref(), args.next(), args.next()) {
+        (None, _, _) => Ok(DEFAULT_PORT),
+        (Some("--port"), Some(port), None) => port.parse().map_err(|_| usage()),
+        _ => Err(usage()),
+    }
+}
+
+#[async_std::main]
+async fn main() -> io::Result<()> {
+    init_logging();
+    let port = parse_args(env::args().skip(1))?;
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    println!("Listening on {}", listener.local_addr()?);
+    serve(listener).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::io::Lines;
+
+    /// Client connection to a test server
+    struct Client {
+        stream: TcpStream,
+        lines: Lines<BufReader<TcpStream>>,
+    }
+
+    impl Client {
+        async fn send(&mut self, line: &str) {
+            self.stream
+                .write_all(format!("{}\n", line).as_bytes())
+                .await
+                .unwrap();
+        }
+
+        async fn recv(&mut self) -> Option<String> {
+            self.lines.next().await.map(Result::unwrap)
+        }
+    }
+
+    /// Start server on a free loopback port and return a function to connect clients
+    async fn server() -> impl Fn() -> task::JoinHandle<Client> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        task::spawn(serve(listener));
+        move || {
+            task::spawn(async move {
+                let stream = TcpStream::connect(addr).await.unwrap();
+                let lines = BufReader::new(stream.clone()).lines();
+                Client { stream, lines }
+            })
+        }
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            Command::parse("load 3,0,4,0,99"),
+            Ok(Command::Load(Memory::from(vec![3, 0, 4, 0, 99])))
+        );
+        assert_eq!(
+            Command::parse("open day05"),
+            Ok(Command::Open("day05".to_string()))
+        );
+        assert_eq!(
+            Command::parse("input 1, -2"),
+            Ok(Command::Input(vec![1, -2]))
+        );
+        assert_eq!(Command::parse(" quit "), Ok(Command::Quit));
+        assert!(Command::parse("open ../secret").is_err());
+        assert!(Command::parse("input x").is_err());
+        assert!(Command::parse("load 1,,2").is_err());
+        assert!(Command::parse("run").is_err());
+    }
+
+    #[test]
+    fn args() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|s| s.to_string()));
+        assert_eq!(args(&[]).unwrap(), DEFAULT_PORT);
+        assert_eq!(args(&["--port", "4000"]).unwrap(), 4000);
+        assert!(args(&["--port"]).is_err());
+        assert!(args(&["--port", "x"]).is_err());
+    }
+
+    #[async_std::test]
+    async fn sessions() {
+        let connect = server().await;
+        let mut client1 = connect().await;
+        let mut client2 = connect().await;
+
+        client1.send("input 1").await;
+        assert_eq!(client1.recv().await.unwrap(), "error No program running");
+
+        // Echo input values until a zero is read
+        client1
+            .send("load 3,13,4,13,1005,13,0,99,0,0,0,0,0,0")
+            .await;
+        assert_eq!(client1.recv().await.unwrap(), "ok");
+        client2.send("open day05").await;
+        assert_eq!(client2.recv().await.unwrap(), "ok");
+
+        client1.send("input 7,8").await;
+        assert_eq!(client1.recv().await.unwrap(), "output 7");
+        assert_eq!(client1.recv().await.unwrap(), "output 8");
+        client2.send("input 1").await;
+        let mut line = client2.recv().await.unwrap();
+        while line == "output 0" {
+            line = client2.recv().await.unwrap();
+        }
+        assert!(line.starts_with("output "));
+        assert!(client2.recv().await.unwrap().starts_with("halt "));
+        client1.send("input 0").await;
+        assert_eq!(client1.recv().await.unwrap(), "output 0");
+        assert_eq!(client1.recv().await.unwrap(), "halt 10");
+
+        // Malformed programs fail with an error instead of stopping silently
+        client1.send("load 109,-1,99").await;
+        assert_eq!(client1.recv().await.unwrap(), "ok");
+        assert_eq!(
+            client1.recv().await.unwrap(),
+            "error Invalid address -1 in instruction at address 0"
+        );
+        client1.send("load 104,1,42").await;
+        assert_eq!(client1.recv().await.unwrap(), "ok");
+        assert_eq!(client1.recv().await.unwrap(), "output 1");
+        assert_eq!(
+            client1.recv().await.unwrap(),
+            "error Unknown opcode 42 at address 2"
+        );
+
+        client2.send("open secret").await;
+        assert_eq!(
+            client2.recv().await.unwrap(),
+            "error Unknown program 'secret'"
+        );
+        client2.send("quit").await;
+        assert_eq!(client2.recv().await, None);
+    }
+}
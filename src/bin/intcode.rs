@@ -0,0 +1,238 @@
+use advent_of_code_2019::intcode::{Session, Value, Vm};
+use advent_of_code_2019::{init_logging, Input};
+use async_std::fs;
+use async_std::io::{self, BufReader};
+use async_std::prelude::*;
+use async_std::stream;
+use futures_util::future::ready;
+use futures_util::stream::TryStreamExt;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::{env, iter};
+
+const USAGE: &str = "Usage: intcode [--ascii] [--record <session> | --replay <session>] <program> \
+                     [--input <file> | <value>...]";
+
+/// Source of input values
+#[derive(Debug)]
+enum Source {
+    /// Values given as command line arguments
+    Args(Vec<String>),
+    /// Values read from a file
+    File(String),
+    /// Values read interactively from stdin
+    Stdin,
+    /// Values taken from a recorded session
+    Replay(String),
+}
+
+/// Command line arguments
+#[derive(Debug)]
+struct Args {
+    /// Exchange input and output as ASCII text instead of numbers
+    ascii: bool,
+    /// Path to session file to record input and output into
+    record: Option<String>,
+    /// Path to program file
+    program: String,
+    /// Where to get input values from
+    source: Source,
+}
+
+impl Args {
+    /// Parse command line arguments
+    fn parse(mut args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let usage = || io::Error::new(io::ErrorKind::InvalidInput, USAGE);
+        let mut ascii = false;
+        let mut record = None;
+        let mut program = None;
+        let mut source = None;
+        let mut values = Vec::new();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--ascii" if program.is_none() => ascii = true,
+                "--record" if program.is_none() && record.is_none() && source.is_none() => {
+                    record = Some(args.next().ok_or_else(usage)?);
+                }
+                "--replay" if program.is_none() && record.is_none() && source.is_none() => {
+                    source = Some(Source::Replay(args.next().ok_or_else(usage)?));
+                }
+                "--input" if program.is_some() && source.is_none() && values.is_empty() => {
+                    source = Some(Source::File(args.next().ok_or_else(usage)?));
+                }
+                "-h" | "--help" | "--record" | "--replay" | "--input" => return Err(usage()),
+                _ if program.is_none() => program = Some(arg),
+                _ if source.is_none() => values.push(arg),
+                _ => return Err(usage()),
+            }
+        }
+        let program = program.ok_or_else(usage)?;
+        let source = source.unwrap_or(if values.is_empty() {
+            Source::Stdin
+        } else {
+            Source::Args(values)
+        });
+        Ok(Self {
+            ascii,
+            record,
+            program,
+            source,
+        })
+    }
+}
+
+/// Convert a line of text to input values. In ASCII mode, every character of the line is used
+/// as a value (followed by a newline), otherwise the line is parsed as comma separated numbers.
+fn parse_line(line: &str, ascii: bool) -> io::Result<Vec<Value>> {
+    if ascii {
+        Ok(line
+            .bytes()
+            .chain(iter::once(b'\n'))
+            .map(Value::from)
+            .collect())
+    } else {
+        line.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect()
+    }
+}
+
+/// Stream of input values parsed from a stream of lines. Errors (including the end of the
+/// lines) are passed on to the vm, which fails with an input error.
+fn parse_lines(
+    lines: impl Stream<Item = io::Result<String>> + Unpin + 'static,
+    ascii: bool,
+) -> impl Stream<Item = io::Result<Value>> + Unpin + 'static {
+    lines
+        .and_then(move |line| ready(parse_line(&line, ascii)))
+        .flat_map(|values| {
+            stream::from_iter(match values {
+                Ok(values) => values.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            })
+        })
+        .chain(stream::once(Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "No more input values",
+        ))))
+}
+
+/// Print an output value. In ASCII mode, values in the ASCII range are printed as characters.
+fn print_value(value: Value, ascii: bool) -> io::Result<()> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    match u8::try_from(value) {
+        Ok(byte) if ascii && byte.is_ascii() => write!(stdout, "{}", byte as char)?,
+        _ => writeln!(stdout, "{}", value)?,
+    }
+    stdout.flush()
+}
+
+#[async_std::main]
+async fn main() -> io::Result<()> {
+    init_logging();
+    let args = Args::parse(env::args().skip(1))?;
+    let program = Input::file(&args.program).await?.memory().await?;
+
+    let mut vm = Vm::new(program);
+    match args.source {
+        Source::Args(values) => {
+            let lines = stream::from_iter(values.into_iter().map(Ok));
+            vm.try_input(parse_lines(lines, args.ascii));
+        }
+        Source::File(path) => {
+            let lines = Input::file(&path).await?.lines();
+            vm.try_input(parse_lines(lines, args.ascii));
+        }
+        Source::Stdin => {
+            let lines = BufReader::new(io::stdin()).lines();
+            vm.try_input(parse_lines(lines, args.ascii));
+        }
+        Source::Replay(path) => {
+            let session: Session = fs::read_to_string(&path)
+                .await?
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            vm.replay(session);
+        }
+    }
+    if args.record.is_some() {
+        vm.record();
+    }
+
+    let ascii = args.ascii;
+    let output = vm.output();
+    let printer = output
+        .map(Ok)
+        .try_for_each(move |value| ready(print_value(value, ascii)));
+    let (result, printed) = vm.run().join(printer).await;
+    if let (Some(path), Some(session)) = (&args.record, vm.take_session()) {
+        fs::write(path, session.to_string()).await?;
+    }
+    // The vm fails if printing fails (since the output is closed), so report the cause first
+    printed?;
+    result?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> io::Result<Args> {
+        Args::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn parse_args() {
+        let args1 = args(&["--ascii", "prog.txt"]).unwrap();
+        assert!(args1.ascii);
+        assert_eq!(args1.program, "prog.txt");
+        assert!(matches!(args1.source, Source::Stdin));
+
+        let args2 = args(&["prog.txt", "1", "2"]).unwrap();
+        assert!(!args2.ascii);
+        assert!(matches!(args2.source, Source::Args(ref v) if v == &["1", "2"]));
+
+        let args3 = args(&["prog.txt", "--input", "in.txt"]).unwrap();
+        assert!(matches!(args3.source, Source::File(ref f) if f == "in.txt"));
+
+        let args4 = args(&["--record", "s.txt", "prog.txt", "1"]).unwrap();
+        assert_eq!(args4.record.as_deref(), Some("s.txt"));
+
+        let args5 = args(&["--replay", "s.txt", "prog.txt"]).unwrap();
+        assert!(matches!(args5.source, Source::Replay(ref f) if f == "s.txt"));
+
+        assert!(args(&[]).is_err());
+        assert!(args(&["--replay", "s.txt", "prog.txt", "1"]).is_err());
+        assert!(args(&["--record", "a.txt", "--replay", "b.txt", "prog.txt"]).is_err());
+        assert!(args(&["prog.txt", "--input"]).is_err());
+        assert!(args(&["prog.txt", "--input", "in.txt", "1"]).is_err());
+    }
+
+    #[test]
+    fn parse_input_lines() {
+        assert_eq!(parse_line("1, -2,3", false).unwrap(), &[1, -2, 3]);
+        assert_eq!(parse_line("", false).unwrap(), &[] as &[Value]);
+        assert!(parse_line("x", false).is_err());
+        assert_eq!(parse_line("Hi", true).unwrap(), &[72, 105, 10]);
+    }
+
+    #[async_std::test]
+    async fn input_ends_with_error() {
+        let lines = stream::from_iter(vec![Ok("1,2".to_string())]);
+        let values: Vec<_> = parse_lines(lines, false).collect().await;
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0].as_ref().unwrap(), &1);
+        assert_eq!(values[1].as_ref().unwrap(), &2);
+        assert_eq!(
+            values[2].as_ref().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
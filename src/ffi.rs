@@ -0,0 +1,242 @@
+//! Advent of Code 2019: C API for embedding the Intcode VM
+//!
+//! The library is also built as a C compatible dynamic library. The C header `include/intcode.h`
+//! is generated from this module by `tests/ffi.rs` (which fails if the header is outdated). A
+//! virtual machine is created from an array of values and driven by stepping or running it until
+//! it produces output, needs input, halts or fails. All words are 64 bit integers. Malformed
+//! programs make the virtual machine fail with an error status (see `intcode_last_error`).
+
+use crate::intcode::{Instruction, Memory, Vm};
+use async_channel::{self as channel, Sender};
+use futures_util::stream::{Stream, StreamExt};
+use futures_util::FutureExt;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::{fmt, ptr, slice};
+
+/// Virtual machine handle
+pub struct IntcodeVm {
+    /// Virtual machine
+    vm: Vm<i64>,
+    /// Sender for input values
+    input: Sender<i64>,
+    /// Output stream of the virtual machine
+    output: Box<dyn Stream<Item = i64> + Unpin>,
+    /// Output values that weren't read yet
+    outputs: VecDeque<i64>,
+    /// Message of the last error
+    error: Option<CString>,
+}
+
+impl fmt::Debug for IntcodeVm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IntcodeVm")
+            .field("vm", &self.vm)
+            .field("outputs", &self.outputs)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+/// Result of stepping or running a virtual machine
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntcodeStatus {
+    /// Instruction executed
+    Ok = 0,
+    /// Output value produced (see `intcode_pop_output`)
+    Output = 1,
+    /// Input value needed to continue (see `intcode_push_input`)
+    NeedInput = 2,
+    /// Program halted
+    Halted = 3,
+    /// Program failed (see `intcode_last_error`)
+    Error = -1,
+}
+
+impl IntcodeVm {
+    /// Execute one instruction
+    fn step(&mut self) -> IntcodeStatus {
+        if self.vm.is_done() {
+            return IntcodeStatus::Halted;
+        }
+        if let Ok(Instruction::Input(_)) = Instruction::decode(self.vm.memory(), self.vm.ip()) {
+            if self.input.is_empty() {
+                return IntcodeStatus::NeedInput;
+            }
+        }
+        // Input is available and output is buffered, so the step never has to wait
+        match self.vm.step().now_or_never() {
+            Some(Ok(())) => (),
+            Some(Err(err)) => {
+                self.error = CString::new(err.to_string()).ok();
+                return IntcodeStatus::Error;
+            }
+            None => return IntcodeStatus::NeedInput,
+        }
+        if let Some(Some(value)) = self.output.next().now_or_never() {
+            self.outputs.push_back(value);
+            IntcodeStatus::Output
+        } else if self.vm.is_done() {
+            IntcodeStatus::Halted
+        } else {
+            IntcodeStatus::Ok
+        }
+    }
+}
+
+/// Create a virtual machine with a program of the given values
+///
+/// # Safety
+///
+/// `values` must point to `len` values. The returned handle must be freed with `intcode_free`.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_new(values: *const i64, len: usize) -> *mut IntcodeVm {
+    let values = if len == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(values, len).to_vec()
+    };
+    let mut vm = Vm::new(Memory::from(values));
+    let (tx, rx) = channel::unbounded();
+    vm.input(rx);
+    let output = Box::new(vm.output());
+    Box::into_raw(Box::new(IntcodeVm {
+        vm,
+        input: tx,
+        output,
+        outputs: VecDeque::new(),
+        error: None,
+    }))
+}
+
+/// Free a virtual machine
+///
+/// # Safety
+///
+/// `vm` must be a handle returned by `intcode_new` (or null) and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_free(vm: *mut IntcodeVm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// Push an input value
+///
+/// # Safety
+///
+/// `vm` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_push_input(vm: *mut IntcodeVm, value: i64) {
+    let _ = (*vm).input.try_send(value);
+}
+
+/// Execute one instruction
+///
+/// # Safety
+///
+/// `vm` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_step(vm: *mut IntcodeVm) -> IntcodeStatus {
+    (*vm).step()
+}
+
+/// Execute instructions until an output value is produced, input is needed, or the program
+/// halts or fails
+///
+/// # Safety
+///
+/// `vm` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_run(vm: *mut IntcodeVm) -> IntcodeStatus {
+    loop {
+        match (*vm).step() {
+            IntcodeStatus::Ok => (),
+            status => return status,
+        }
+    }
+}
+
+/// Take the oldest output value that wasn't read yet. Returns false if there is none.
+///
+/// # Safety
+///
+/// `vm` must be a valid handle and `value` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_pop_output(vm: *mut IntcodeVm, value: *mut i64) -> bool {
+    match (*vm).outputs.pop_front() {
+        Some(output) => {
+            *value = output;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Return the memory size
+///
+/// # Safety
+///
+/// `vm` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_memory_size(vm: *const IntcodeVm) -> usize {
+    (*vm).vm.memory().size()
+}
+
+/// Read the value at the given address. Returns false if the address is out of range.
+///
+/// # Safety
+///
+/// `vm` must be a valid handle and `value` must point to writable memory.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_read(vm: *const IntcodeVm, addr: usize, value: *mut i64) -> bool {
+    let memory = (*vm).vm.memory();
+    if addr < memory.size() {
+        *value = memory.get(addr);
+        true
+    } else {
+        false
+    }
+}
+
+/// Write a value to the given address. Returns false if the address is out of range.
+///
+/// # Safety
+///
+/// `vm` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_write(vm: *mut IntcodeVm, addr: usize, value: i64) -> bool {
+    let memory = (*vm).vm.memory_mut();
+    if addr < memory.size() {
+        memory.set(addr, value);
+        true
+    } else {
+        false
+    }
+}
+
+/// Return the instruction pointer
+///
+/// # Safety
+///
+/// `vm` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_ip(vm: *const IntcodeVm) -> usize {
+    (*vm).vm.ip()
+}
+
+/// Return the message of the last error (or null). The message is valid until the handle is
+/// freed or the next error occurs.
+///
+/// # Safety
+///
+/// `vm` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn intcode_last_error(vm: *const IntcodeVm) -> *const c_char {
+    match &(*vm).error {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
@@ -1,18 +1,27 @@
 //! Advent of Code 2019: puzzle input reading
 
-use crate::intcode::{Memory, Value};
-use async_std::fs::File;
-use async_std::io::{self, BufReader};
-use async_std::path::PathBuf;
-use async_std::prelude::*;
+use crate::intcode::{Memory, Word};
+use crate::runtime::{self, File};
 use futures_util::future::ready;
-use futures_util::stream::TryStreamExt;
-use std::error;
+use futures_util::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use futures_util::stream::{Stream, TryStreamExt};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::{error, fmt, io};
+use tracing::debug;
 
 /// Path to puzzle input files
 const INPUT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/input");
 
+/// Log a parse error of the given input data and convert it into an I/O error
+fn parse_error<E>(data: &str, err: E) -> io::Error
+where
+    E: Into<Box<dyn error::Error + Send + Sync>> + fmt::Display,
+{
+    debug!(data, error = %err, "Failed to parse input");
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
 /// Puzzle input
 #[derive(Debug)]
 pub struct Input {
@@ -30,7 +39,18 @@ impl Input {
         let mut filename: PathBuf = INPUT_PATH.into();
         filename.push(name);
         filename.set_extension("txt");
-        let reader = BufReader::new(File::open(filename).await?);
+        Self::file(filename).await
+    }
+
+    /// Open puzzle input from the given file path
+    pub async fn file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        debug!(path = %path.display(), "Opening input file");
+        let file = runtime::open(path).await.map_err(|err| {
+            debug!(path = %path.display(), error = %err, "Failed to open input file");
+            err
+        })?;
+        let reader = BufReader::new(file);
         Ok(Input { reader })
     }
 
@@ -45,12 +65,8 @@ impl Input {
         T: FromStr,
         T::Err: error::Error + Send + Sync + 'static,
     {
-        self.lines().and_then(|line| {
-            ready(
-                line.parse()
-                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
-            )
-        })
+        self.lines()
+            .and_then(|line| ready(line.parse().map_err(|e| parse_error(&line, e))))
     }
 
     /// Stream of comma separated values
@@ -72,19 +88,49 @@ impl Input {
             ready(
                 values
                     .iter()
-                    .map(|value| {
-                        value
-                            .parse()
-                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-                    })
+                    .map(|value| value.parse().map_err(|e| parse_error(value, e)))
                     .collect(),
             )
         })
     }
 
-    /// Intcode memory (parsed from comma separated values)
+    /// Intcode memory (parsed from comma separated values or read from the binary format)
     pub async fn memory(self) -> io::Result<Memory> {
-        let data = self.parsed_csv_lines::<Value>().try_concat().await?;
-        Ok(Memory::from(data))
+        self.memory_of().await
+    }
+
+    /// Intcode memory with words of the given type (parsed from comma separated values or read
+    /// from the binary format)
+    pub async fn memory_of<V: Word + FromStr>(mut self) -> io::Result<Memory<V>> {
+        let mut data = Vec::new();
+        self.reader.read_to_end(&mut data).await?;
+        if Memory::<V>::is_binary(&data) {
+            debug!(len = data.len(), "Reading binary program");
+            Memory::from_binary(&data).map_err(|e| parse_error("<binary program>", e))
+        } else {
+            let text = String::from_utf8(data).map_err(|e| parse_error("<program>", e))?;
+            text.parse().map_err(|e| parse_error("<program>", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[crate::runtime::test]
+    async fn read_lines() {
+        let input = Input::day(1).await.unwrap();
+        let lines = input.parsed_lines::<u32>().try_collect::<Vec<_>>();
+        let lines = lines.await.unwrap();
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[0], 104451);
+    }
+
+    #[crate::runtime::test]
+    async fn read_memory() {
+        let memory = Input::day(5).await.unwrap().memory().await.unwrap();
+        assert!(memory.size() > 0);
+        assert!(Input::open("missing").await.is_err());
     }
 }
@@ -0,0 +1,137 @@
+//! Advent of Code 2019: Intcode VM on a dedicated thread
+
+use super::cancel::CancellationToken;
+use super::report::Report;
+use super::vm::Vm;
+use crate::runtime;
+use futures_util::future;
+use futures_util::stream::{Stream, StreamExt};
+use intcode_core::{Error, Value, Word};
+use std::pin::Pin;
+use std::sync::mpsc::{self, SendError};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::{panic, thread};
+
+/// Vm and the result of running it, returned by the vm thread
+type Outcome<V> = (Vm<V>, Result<Report, Error>);
+
+/// Stream of input values received from a std channel
+///
+/// Senders wake up the stream after sending a value (see `BlockingVm::send_input`), so the vm
+/// thread never blocks while polling. Once all senders are gone, the stream waits forever (the
+/// vm is cancelled when its handle is dropped).
+struct InputStream<V> {
+    rx: mpsc::Receiver<V>,
+    waker: Arc<Mutex<Option<Waker>>>,
+}
+
+impl<V> Stream for InputStream<V> {
+    type Item = V;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<V>> {
+        // Register waker before checking the channel, so that no value sent in between is missed
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+        match self.rx.try_recv() {
+            Ok(value) => Poll::Ready(Some(value)),
+            Err(_) => Poll::Pending,
+        }
+    }
+}
+
+/// Virtual machine running on a dedicated thread, for use from synchronous code
+///
+/// The vm runs on its own OS thread (driven by a local executor), input values are sent and
+/// output values are received through blocking std channels. The thread ends when the program
+/// halts or fails, or when the handle is dropped (which cancels the vm).
+#[derive(Debug)]
+pub struct BlockingVm<V: Word = Value> {
+    /// Sender for input values
+    input: mpsc::Sender<V>,
+    /// Waker of the input stream (woken after sending input values)
+    waker: Arc<Mutex<Option<Waker>>>,
+    /// Receiver for output values
+    output: mpsc::Receiver<V>,
+    /// Token to cancel the vm when the handle is dropped
+    token: CancellationToken,
+    /// Handle of the vm thread (taken when joining)
+    thread: Option<thread::JoinHandle<Outcome<V>>>,
+}
+
+impl<V: Word> BlockingVm<V> {
+    /// Start running the given vm on a new thread
+    ///
+    /// The vm must not have an input or output stream set, since input and output are connected
+    /// to the channels of the returned handle.
+    pub fn spawn(mut vm: Vm<V>) -> Self {
+        let (input_tx, input_rx) = mpsc::channel();
+        let (output_tx, output_rx) = mpsc::channel();
+        let waker = Arc::new(Mutex::new(None));
+        let token = CancellationToken::new();
+        vm.input(InputStream {
+            rx: input_rx,
+            waker: waker.clone(),
+        });
+        let mut output = vm.output();
+        let thread_token = token.clone();
+        let thread = thread::spawn(move || {
+            runtime::block_on(async {
+                let forward = async {
+                    while let Some(value) = output.next().await {
+                        // Receiver only goes away with the handle, which cancels the vm anyway
+                        let _ = output_tx.send(value);
+                    }
+                    Ok(())
+                };
+                let (result, _) =
+                    future::join(vm.run_with(&thread_token), thread_token.run(forward)).await;
+                (vm, result)
+            })
+        });
+        Self {
+            input: input_tx,
+            waker,
+            output: output_rx,
+            token,
+            thread: Some(thread),
+        }
+    }
+
+    /// Send input value to the vm
+    ///
+    /// Fails (returning the value) if the vm thread has ended.
+    pub fn send_input(&self, value: V) -> Result<(), SendError<V>> {
+        self.input.send(value)?;
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Wait for the next output value of the vm
+    ///
+    /// Returns `None` once the program halted (or failed) and all output values were received.
+    pub fn recv_output(&self) -> Option<V> {
+        self.output.recv().ok()
+    }
+
+    /// Wait for the program to halt and return the vm with the result of running it
+    ///
+    /// Output values that weren't received are discarded. Blocks forever if the program waits
+    /// for input that is never sent.
+    pub fn join(mut self) -> Outcome<V> {
+        let thread = self.thread.take().expect("Vm thread already joined");
+        thread
+            .join()
+            .unwrap_or_else(|err| panic::resume_unwind(err))
+    }
+}
+
+impl<V: Word> Drop for BlockingVm<V> {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.token.cancel();
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Advent of Code 2019: Intcode cancellation
+
+use crate::runtime;
+use async_channel::{self as channel, Receiver, Sender};
+use futures_util::future;
+use intcode_core::Error;
+use std::future::Future;
+use std::pin::pin;
+use std::time::{Duration, Instant};
+
+/// Cancellation token
+///
+/// A token can be used to interrupt running virtual machines (or any group of them), either
+/// explicitly by calling `cancel` or implicitly when its deadline passes. Clones of a token share
+/// their cancellation state, so a clone can be handed to another task to cancel from there.
+#[derive(Debug, Clone)]
+pub struct CancellationToken {
+    /// Sender side of a channel that is closed on cancellation (never used for sending)
+    tx: Sender<()>,
+    /// Receiver side of the channel, used to wait for cancellation
+    rx: Receiver<()>,
+    /// Optional point in time at which running futures time out
+    deadline: Option<Instant>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        let (tx, rx) = channel::bounded(1);
+        Self {
+            tx,
+            rx,
+            deadline: None,
+        }
+    }
+}
+
+impl CancellationToken {
+    /// Create new cancellation token without deadline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create new cancellation token that times out at the given deadline
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..Self::default()
+        }
+    }
+
+    /// Create new cancellation token that times out after the given duration
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Cancel all futures running with this token (or a clone of it)
+    pub fn cancel(&self) {
+        self.tx.close();
+    }
+
+    /// Returns true if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Returns the deadline of the token
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Return a guard that cancels the token when it is dropped
+    pub(super) fn drop_guard(&self) -> DropGuard {
+        DropGuard(self.clone())
+    }
+
+    /// Run the given future until it completes, the token is cancelled or its deadline passes
+    ///
+    /// On cancellation or timeout, the future is dropped and `Error::Cancelled` or
+    /// `Error::TimedOut` is returned. Virtual machines only change their state after an
+    /// instruction completed, so interrupted machines keep their partial state and can be resumed
+    /// by running them again.
+    pub async fn run<T>(&self, future: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        let cancelled = async {
+            // Nothing is ever sent, so receiving only returns once the channel is closed
+            let _ = self.rx.recv().await;
+            Err(Error::Cancelled)
+        };
+        let timed_out = async {
+            match self.deadline {
+                Some(deadline) => {
+                    runtime::sleep(deadline.saturating_duration_since(Instant::now())).await;
+                    Err(Error::TimedOut)
+                }
+                None => future::pending().await,
+            }
+        };
+        // The future is polled first, so it wins if it completes at the same time
+        let interrupted = async {
+            future::select(pin!(cancelled), pin!(timed_out))
+                .await
+                .factor_first()
+                .0
+        };
+        future::select(pin!(future), pin!(interrupted))
+            .await
+            .factor_first()
+            .0
+    }
+}
+
+/// Guard that cancels a token when dropped (see `CancellationToken::drop_guard`)
+#[derive(Debug)]
+pub(super) struct DropGuard(CancellationToken);
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
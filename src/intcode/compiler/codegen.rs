@@ -0,0 +1,492 @@
+//! Advent of Code 2019: Intcode compiler code generation
+//!
+//! Every function (including the main program) uses a stack frame addressed relative to the
+//! relative base. The frame starts with the return address (offset 0) and the return value
+//! (offset 1), followed by the parameters, the local variables and temporary values. To call a
+//! function, the caller reserves a new frame after its temporaries in use, stores the arguments
+//! and the return address into it, moves the relative base to the new frame and jumps to the
+//! function. On return, the callee stores the return value into its frame and jumps back to the
+//! return address, where the caller restores its relative base.
+
+use super::parser::{BinaryOp, Expr, Function, Program, Stmt, UnaryOp};
+use super::{CompileError, Pos};
+use crate::intcode::{Address, Instruction, Param};
+use std::collections::HashMap;
+
+/// Frame offset of the return address
+const RETURN_ADDRESS: isize = 0;
+/// Frame offset of the return value
+const RETURN_VALUE: isize = 1;
+/// Frame offset of the first parameter
+const PARAMS: isize = 2;
+
+/// Builtin functions and their number of parameters
+const BUILTINS: [(&str, usize); 2] = [("input", 0), ("output", 1)];
+
+/// Jump target whose address might not be known yet
+#[derive(Debug, Clone, Copy)]
+struct Label(usize);
+
+/// Stack frame layout of the function being compiled
+#[derive(Debug, Default)]
+struct Frame<'a> {
+    /// Frame offsets of all parameters and local variables
+    slots: HashMap<&'a str, isize>,
+    /// Names of variables that have been declared so far
+    declared: Vec<&'a str>,
+    /// Frame offset after the last local variable
+    locals_end: isize,
+    /// Frame offset of the next free temporary
+    temps: isize,
+}
+
+impl<'a> Frame<'a> {
+    /// Create frame for a function with the given parameters and body
+    fn new(params: &'a [String], body: &'a [Stmt]) -> Self {
+        let mut frame = Self::default();
+        for name in params.iter() {
+            frame.slot(name);
+            frame.declared.push(name);
+        }
+        frame.collect_locals(body);
+        frame.locals_end = PARAMS + frame.slots.len() as isize;
+        frame.temps = frame.locals_end;
+        frame
+    }
+
+    /// Assign a slot to the given variable unless it already has one
+    fn slot(&mut self, name: &'a str) {
+        let offset = PARAMS + self.slots.len() as isize;
+        self.slots.entry(name).or_insert(offset);
+    }
+
+    /// Assign slots to all variables declared in the given statements
+    fn collect_locals(&mut self, stmts: &'a [Stmt]) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Let(name, _, _) => self.slot(name),
+                Stmt::If(_, then, otherwise) => {
+                    self.collect_locals(then);
+                    self.collect_locals(otherwise);
+                }
+                Stmt::While(_, body) => self.collect_locals(body),
+                _ => (),
+            }
+        }
+    }
+
+    /// Return parameter for accessing the given (declared) variable
+    fn var(&self, name: &str, pos: Pos) -> Result<Param<i64>, CompileError> {
+        if self.declared.contains(&name) {
+            Ok(Param::Relative(self.slots[name]))
+        } else {
+            Err(CompileError::new(
+                pos,
+                format!("Undefined variable '{}'", name),
+            ))
+        }
+    }
+
+    /// Allocate a temporary
+    fn alloc(&mut self) -> Param<i64> {
+        self.temps += 1;
+        Param::Relative(self.temps - 1)
+    }
+}
+
+/// Code generator
+#[derive(Debug)]
+pub(super) struct Codegen {
+    /// Function that checks whether a value fits into a word of the target word type
+    fits: fn(i64) -> bool,
+    /// Generated code
+    code: Vec<i64>,
+    /// Addresses of labels (once placed)
+    labels: Vec<Option<Address>>,
+    /// Code positions that need to be patched with the address of a label
+    fixups: Vec<(usize, Label)>,
+    /// Labels and number of parameters of all functions
+    functions: HashMap<String, (Label, usize)>,
+}
+
+impl Codegen {
+    /// Create code generator for a word type whose range is checked by the given function
+    pub fn new(fits: fn(i64) -> bool) -> Self {
+        Self {
+            fits,
+            code: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Generate code for the given program. The stack begins right after the returned code.
+    pub fn program(mut self, program: &Program) -> Result<Vec<i64>, CompileError> {
+        for function in &program.functions {
+            if BUILTINS.iter().any(|(name, _)| *name == function.name)
+                || self.functions.contains_key(&function.name)
+            {
+                return Err(CompileError::new(
+                    function.pos,
+                    format!("Function '{}' is already defined", function.name),
+                ));
+            }
+            let params = &function.params;
+            if let Some(name) = params
+                .iter()
+                .enumerate()
+                .find_map(|(i, name)| params[..i].contains(name).then_some(name))
+            {
+                return Err(CompileError::new(
+                    function.pos,
+                    format!(
+                        "Parameter '{}' of function '{}' is defined twice",
+                        name, function.name
+                    ),
+                ));
+            }
+            let label = self.label();
+            self.functions
+                .insert(function.name.clone(), (label, function.params.len()));
+        }
+
+        let stack = self.label();
+        self.emit_with_label(
+            Instruction::AdjustRelativeBase(Param::Immediate(0)),
+            0,
+            stack,
+        );
+        let mut frame = Frame::new(&[], &program.main);
+        self.block(&mut frame, &program.main, true)?;
+        self.emit(Instruction::Done);
+
+        for function in &program.functions {
+            self.function(function)?;
+        }
+
+        self.place(stack);
+        for (pos, label) in self.fixups {
+            self.code[pos] = self.labels[label.0].expect("Label not placed") as i64;
+        }
+        Ok(self.code)
+    }
+
+    /// Create new label
+    fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Place label at the current code position
+    fn place(&mut self, label: Label) {
+        self.labels[label.0] = Some(self.code.len());
+    }
+
+    /// Emit instruction
+    fn emit(&mut self, instruction: Instruction<i64>) {
+        self.code.extend(instruction.encode());
+    }
+
+    /// Emit instruction with the given parameter set to the address of the given label
+    fn emit_with_label(&mut self, instruction: Instruction<i64>, n: usize, label: Label) {
+        self.fixups.push((self.code.len() + 1 + n, label));
+        self.emit(instruction);
+    }
+
+    /// Emit instruction to copy a value
+    fn mov(&mut self, src: Param<i64>, dest: Param<i64>) {
+        if src != dest {
+            self.emit(Instruction::Add(src, Param::Immediate(0), dest));
+        }
+    }
+
+    /// Emit unconditional jump to the given label
+    fn jump(&mut self, label: Label) {
+        let instruction = Instruction::JumpIfZero(Param::Immediate(0), Param::Immediate(0));
+        self.emit_with_label(instruction, 1, label);
+    }
+
+    /// Emit instruction to set dest to 1 if value is not zero, 0 otherwise
+    fn truth(&mut self, value: Param<i64>, dest: Param<i64>) {
+        self.emit(Instruction::Equals(
+            value,
+            Param::Immediate(0),
+            dest.clone(),
+        ));
+        self.emit(Instruction::Equals(dest.clone(), Param::Immediate(0), dest));
+    }
+
+    /// Generate code for a function
+    fn function(&mut self, function: &Function) -> Result<(), CompileError> {
+        let (label, _) = self.functions[&function.name];
+        self.place(label);
+        let mut frame = Frame::new(&function.params, &function.body);
+        self.block(&mut frame, &function.body, false)?;
+        self.ret(Param::Immediate(0));
+        Ok(())
+    }
+
+    /// Emit return from function with the given value
+    fn ret(&mut self, value: Param<i64>) {
+        self.mov(value, Param::Relative(RETURN_VALUE));
+        self.emit(Instruction::JumpIfZero(
+            Param::Immediate(0),
+            Param::Relative(RETURN_ADDRESS),
+        ));
+    }
+
+    /// Generate code for a block of statements
+    fn block<'a>(
+        &mut self,
+        frame: &mut Frame<'a>,
+        stmts: &'a [Stmt],
+        main: bool,
+    ) -> Result<(), CompileError> {
+        for stmt in stmts {
+            self.statement(frame, stmt, main)?;
+            frame.temps = frame.locals_end;
+        }
+        Ok(())
+    }
+
+    /// Generate code for a statement
+    fn statement<'a>(
+        &mut self,
+        frame: &mut Frame<'a>,
+        stmt: &'a Stmt,
+        main: bool,
+    ) -> Result<(), CompileError> {
+        match stmt {
+            Stmt::Let(name, expr, pos) => {
+                let value = self.expr(frame, expr)?;
+                frame.declared.push(name);
+                let var = frame.var(name, *pos)?;
+                self.mov(value, var);
+            }
+            Stmt::Assign(name, expr, pos) => {
+                let var = frame.var(name, *pos)?;
+                let value = self.expr(frame, expr)?;
+                self.mov(value, var);
+            }
+            Stmt::If(cond, then, otherwise) => {
+                let otherwise_label = self.label();
+                let end_label = self.label();
+                let cond = self.expr(frame, cond)?;
+                let instruction = Instruction::JumpIfZero(cond, Param::Immediate(0));
+                self.emit_with_label(instruction, 1, otherwise_label);
+                self.block(frame, then, main)?;
+                if !otherwise.is_empty() {
+                    self.jump(end_label);
+                }
+                self.place(otherwise_label);
+                self.block(frame, otherwise, main)?;
+                self.place(end_label);
+            }
+            Stmt::While(cond, body) => {
+                let top_label = self.label();
+                let end_label = self.label();
+                self.place(top_label);
+                let cond = self.expr(frame, cond)?;
+                let instruction = Instruction::JumpIfZero(cond, Param::Immediate(0));
+                self.emit_with_label(instruction, 1, end_label);
+                self.block(frame, body, main)?;
+                self.jump(top_label);
+                self.place(end_label);
+            }
+            Stmt::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.expr(frame, expr)?,
+                    None => Param::Immediate(0),
+                };
+                if main {
+                    self.emit(Instruction::Done);
+                } else {
+                    self.ret(value);
+                }
+            }
+            Stmt::Expr(expr) => {
+                self.expr(frame, expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Generate code for an expression. Returns a parameter to access the result.
+    fn expr<'a>(
+        &mut self,
+        frame: &mut Frame<'a>,
+        expr: &'a Expr,
+    ) -> Result<Param<i64>, CompileError> {
+        match expr {
+            Expr::Number(n, _) if (self.fits)(*n) => Ok(Param::Immediate(*n)),
+            Expr::Number(n, pos) => Err(CompileError::new(
+                *pos,
+                format!("Value {} doesn't fit into a word", n),
+            )),
+            Expr::Var(name, pos) => frame.var(name, *pos),
+            Expr::Call(name, args, pos) => self.call(frame, name, args, *pos),
+            Expr::Unary(op, expr) => {
+                let mark = frame.temps;
+                let value = self.expr(frame, expr)?;
+                frame.temps = mark;
+                let dest = frame.alloc();
+                match op {
+                    UnaryOp::Neg => self.emit(Instruction::Multiply(
+                        value,
+                        Param::Immediate(-1),
+                        dest.clone(),
+                    )),
+                    UnaryOp::Not => self.emit(Instruction::Equals(
+                        value,
+                        Param::Immediate(0),
+                        dest.clone(),
+                    )),
+                }
+                Ok(dest)
+            }
+            Expr::Binary(op @ BinaryOp::And, lhs, rhs)
+            | Expr::Binary(op @ BinaryOp::Or, lhs, rhs) => {
+                let dest = frame.alloc();
+                let end_label = self.label();
+                let mark = frame.temps;
+                let value = self.expr(frame, lhs)?;
+                self.truth(value, dest.clone());
+                frame.temps = mark;
+                let instruction = if *op == BinaryOp::And {
+                    Instruction::JumpIfZero(dest.clone(), Param::Immediate(0))
+                } else {
+                    Instruction::JumpIfNotZero(dest.clone(), Param::Immediate(0))
+                };
+                self.emit_with_label(instruction, 1, end_label);
+                let value = self.expr(frame, rhs)?;
+                self.truth(value, dest.clone());
+                frame.temps = mark;
+                self.place(end_label);
+                Ok(dest)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let mark = frame.temps;
+                let a = self.expr(frame, lhs)?;
+                let b = self.expr(frame, rhs)?;
+                let scratch = frame.alloc();
+                frame.temps = mark;
+                let dest = frame.alloc();
+                match op {
+                    BinaryOp::Add => self.emit(Instruction::Add(a, b, dest.clone())),
+                    BinaryOp::Sub => {
+                        self.emit(Instruction::Multiply(
+                            b,
+                            Param::Immediate(-1),
+                            scratch.clone(),
+                        ));
+                        self.emit(Instruction::Add(a, scratch, dest.clone()));
+                    }
+                    BinaryOp::Mul => self.emit(Instruction::Multiply(a, b, dest.clone())),
+                    BinaryOp::Lt => self.emit(Instruction::LessThan(a, b, dest.clone())),
+                    BinaryOp::Gt => self.emit(Instruction::LessThan(b, a, dest.clone())),
+                    BinaryOp::Le => {
+                        self.emit(Instruction::LessThan(b, a, scratch.clone()));
+                        self.emit(Instruction::Equals(
+                            scratch,
+                            Param::Immediate(0),
+                            dest.clone(),
+                        ));
+                    }
+                    BinaryOp::Ge => {
+                        self.emit(Instruction::LessThan(a, b, scratch.clone()));
+                        self.emit(Instruction::Equals(
+                            scratch,
+                            Param::Immediate(0),
+                            dest.clone(),
+                        ));
+                    }
+                    BinaryOp::Eq => self.emit(Instruction::Equals(a, b, dest.clone())),
+                    BinaryOp::Ne => {
+                        self.emit(Instruction::Equals(a, b, scratch.clone()));
+                        self.emit(Instruction::Equals(
+                            scratch,
+                            Param::Immediate(0),
+                            dest.clone(),
+                        ));
+                    }
+                    BinaryOp::And | BinaryOp::Or => unreachable!(),
+                }
+                Ok(dest)
+            }
+        }
+    }
+
+    /// Generate code for a function call
+    fn call<'a>(
+        &mut self,
+        frame: &mut Frame<'a>,
+        name: &str,
+        args: &'a [Expr],
+        pos: Pos,
+    ) -> Result<Param<i64>, CompileError> {
+        let params = BUILTINS
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, params)| *params)
+            .or_else(|| self.functions.get(name).map(|(_, params)| *params))
+            .ok_or_else(|| CompileError::new(pos, format!("Undefined function '{}'", name)))?;
+        if args.len() != params {
+            return Err(CompileError::new(
+                pos,
+                format!(
+                    "Function '{}' takes {} arguments but {} were given",
+                    name,
+                    params,
+                    args.len()
+                ),
+            ));
+        }
+
+        match name {
+            "input" => {
+                let dest = frame.alloc();
+                self.emit(Instruction::Input(dest.clone()));
+                Ok(dest)
+            }
+            "output" => {
+                let mark = frame.temps;
+                let value = self.expr(frame, &args[0])?;
+                frame.temps = mark;
+                self.emit(Instruction::Output(value));
+                Ok(Param::Immediate(0))
+            }
+            _ => {
+                // Reserve the callee's frame header and parameters before evaluating arguments
+                let base = frame.temps;
+                frame.temps += PARAMS + args.len() as isize;
+                for (i, arg) in args.iter().enumerate() {
+                    let mark = frame.temps;
+                    let value = self.expr(frame, arg)?;
+                    self.mov(value, Param::Relative(base + PARAMS + i as isize));
+                    frame.temps = mark;
+                }
+                let (function_label, _) = self.functions[name];
+                let return_label = self.label();
+                let instruction = Instruction::Add(
+                    Param::Immediate(0),
+                    Param::Immediate(0),
+                    Param::Relative(base + RETURN_ADDRESS),
+                );
+                self.emit_with_label(instruction, 0, return_label);
+                self.emit(Instruction::AdjustRelativeBase(Param::Immediate(
+                    base as i64,
+                )));
+                self.jump(function_label);
+                self.place(return_label);
+                self.emit(Instruction::AdjustRelativeBase(Param::Immediate(
+                    -(base as i64),
+                )));
+                frame.temps = base;
+                let dest = frame.alloc();
+                self.mov(Param::Relative(base + RETURN_VALUE), dest.clone());
+                Ok(dest)
+            }
+        }
+    }
+}
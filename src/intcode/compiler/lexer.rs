@@ -0,0 +1,111 @@
+//! Advent of Code 2019: Intcode compiler lexer
+
+use super::{CompileError, Pos};
+use std::fmt;
+
+/// Source token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Token {
+    /// Integer literal
+    Number(i64),
+    /// Identifier (name of variable or function)
+    Ident(String),
+    /// Keyword `fn`
+    Fn,
+    /// Keyword `let`
+    Let,
+    /// Keyword `if`
+    If,
+    /// Keyword `else`
+    Else,
+    /// Keyword `while`
+    While,
+    /// Keyword `return`
+    Return,
+    /// Punctuation or operator
+    Symbol(&'static str),
+    /// End of source
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Fn => write!(f, "fn"),
+            Token::Let => write!(f, "let"),
+            Token::If => write!(f, "if"),
+            Token::Else => write!(f, "else"),
+            Token::While => write!(f, "while"),
+            Token::Return => write!(f, "return"),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
+/// Symbols, longest first so that e.g. `<=` is preferred over `<`
+const SYMBOLS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||", "(", ")", "{", "}", ",", ";", "=", "+", "-", "*", "<", ">",
+    "!",
+];
+
+/// Split source into tokens with their positions. The last token is always `Token::Eof`.
+pub(super) fn tokenize(source: &str) -> Result<Vec<(Token, Pos)>, CompileError> {
+    let mut tokens = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with("//") || rest.starts_with('#') {
+                break;
+            }
+            let pos = Pos {
+                line: line_idx + 1,
+                column: line[..line.len() - rest.len()].chars().count() + 1,
+            };
+            let c = rest.chars().next().unwrap();
+            let len = if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let number = rest[..len]
+                    .parse()
+                    .map_err(|_| CompileError::new(pos, "Number too large"))?;
+                tokens.push((Token::Number(number), pos));
+                len
+            } else if c.is_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                let token = match &rest[..len] {
+                    "fn" => Token::Fn,
+                    "let" => Token::Let,
+                    "if" => Token::If,
+                    "else" => Token::Else,
+                    "while" => Token::While,
+                    "return" => Token::Return,
+                    name => Token::Ident(name.to_string()),
+                };
+                tokens.push((token, pos));
+                len
+            } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                tokens.push((Token::Symbol(symbol), pos));
+                symbol.len()
+            } else {
+                return Err(CompileError::new(
+                    pos,
+                    format!("Unexpected character '{}'", c),
+                ));
+            };
+            rest = &rest[len..];
+        }
+    }
+    let eof = Pos {
+        line: source.lines().count() + 1,
+        column: 1,
+    };
+    tokens.push((Token::Eof, eof));
+    Ok(tokens)
+}
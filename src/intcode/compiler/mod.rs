@@ -0,0 +1,244 @@
+//! Advent of Code 2019: Intcode compiler
+//!
+//! Compiles programs written in a small structured language to Intcode. A program consists of
+//! function definitions and top level statements, which form the main program:
+//!
+//! ```text
+//! fn fib(n) {
+//!     if n < 2 { return n; }
+//!     return fib(n - 1) + fib(n - 2);
+//! }
+//!
+//! let n = input();
+//! while n > 0 {
+//!     output(fib(n));
+//!     n = n - 1;
+//! }
+//! ```
+//!
+//! All values are integers. Supported are variables (`let`, assignment), `if`/`else`, `while`,
+//! functions with parameters and `return` (also recursively), the operators `+ - * < > <= >= ==
+//! != && || !` and unary `-`, and the builtin functions `input()` and `output(value)`. Comments
+//! start with `//` or `#`. Conditions are true if not zero and comparisons evaluate to 1 or 0.
+//! Variables are local to the function they are declared in and must be declared before they're
+//! used. A `return` in the main program halts the program. The stack of compiled programs has a
+//! fixed size of 1024 words, which limits the recursion depth (see `STACK_SIZE`).
+
+mod codegen;
+mod lexer;
+mod parser;
+
+use intcode_core::{Memory, Word};
+use std::{error, fmt};
+
+/// Stack size (in words) of compiled programs
+///
+/// The stack is placed after the code at the end of memory. Every active function call uses a
+/// stack frame (return address, return value, parameters, local variables and temporaries), so
+/// recursion depth is limited to about `STACK_SIZE` divided by the frame size. Deeper recursion
+/// makes the program fail with `Error::InvalidAddress` when accessing memory beyond its end.
+const STACK_SIZE: usize = 1024;
+
+/// Position in the source code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pos {
+    /// Line number (starting at 1)
+    pub line: usize,
+    /// Column number (starting at 1)
+    pub column: usize,
+}
+
+impl fmt::Display for Pos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Error returned when compiling a program fails
+#[derive(Debug)]
+pub struct CompileError {
+    /// Position of the error in the source code
+    pub pos: Pos,
+    /// Description of the error
+    pub message: String,
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.pos, self.message)
+    }
+}
+
+impl error::Error for CompileError {}
+
+impl CompileError {
+    /// Create new error at the given position
+    fn new(pos: Pos, message: impl Into<String>) -> Self {
+        Self {
+            pos,
+            message: message.into(),
+        }
+    }
+}
+
+/// Compile source code to an Intcode program
+pub fn compile<V: Word>(source: &str) -> Result<Memory<V>, CompileError> {
+    let tokens = lexer::tokenize(source)?;
+    let program = parser::Parser::new(tokens).program()?;
+    // Literals are checked while generating code, so only addresses can be out of range here
+    let code = codegen::Codegen::new(|value| V::from_i64(value).is_some()).program(&program)?;
+    let mut words = Vec::with_capacity(code.len() + STACK_SIZE);
+    for value in code {
+        words.push(V::from_i64(value).ok_or_else(|| {
+            CompileError::new(
+                Pos { line: 1, column: 1 },
+                "Program is too large for the word type",
+            )
+        })?);
+    }
+    words.resize(words.len() + STACK_SIZE, V::from(0));
+    Ok(Memory::from(words))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::{Error, Vm};
+    use async_std::stream;
+
+    async fn run(source: &str, input: Vec<i64>) -> Vec<i64> {
+        let mut vm = Vm::new(compile(source).unwrap());
+        vm.input(stream::from_iter(input));
+        vm.run_and_collect().await.unwrap()
+    }
+
+    #[crate::runtime::test]
+    async fn arithmetic() {
+        let source = "output(1 + 2 * 3); output((1 + 2) * 3); output(10 - 4 - 3); output(-5);";
+        assert_eq!(run(source, vec![]).await, [7, 9, 3, -5]);
+    }
+
+    #[crate::runtime::test]
+    async fn comparisons() {
+        let source = "
+            let a = input();
+            let b = input();
+            output(a < b); output(a > b); output(a <= b); output(a >= b);
+            output(a == b); output(a != b); output(!a);
+        ";
+        assert_eq!(run(source, vec![1, 2]).await, [1, 0, 1, 0, 0, 1, 0]);
+        assert_eq!(run(source, vec![2, 2]).await, [0, 0, 1, 1, 1, 0, 0]);
+        assert_eq!(run(source, vec![0, -1]).await, [0, 1, 0, 1, 0, 1, 1]);
+    }
+
+    #[crate::runtime::test]
+    async fn control_flow() {
+        let source = "
+            # Sum of all numbers up to the input
+            let n = input();
+            let sum = 0;
+            while n > 0 {
+                sum = sum + n;
+                n = n - 1;
+            }
+            if sum > 100 { output(1); } else if sum > 10 { output(2); } else { output(3); }
+            output(sum);
+        ";
+        assert_eq!(run(source, vec![20]).await, [1, 210]);
+        assert_eq!(run(source, vec![5]).await, [2, 15]);
+        assert_eq!(run(source, vec![2]).await, [3, 3]);
+    }
+
+    #[crate::runtime::test]
+    async fn functions() {
+        let source = "
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            fn fact(n) {
+                if n == 0 { return 1; }
+                return n * fact(n - 1);
+            }
+            fn add3(a, b, c) { return a + b + c; }
+            fn nothing() { }
+            output(fib(input()));
+            output(fact(10));
+            output(add3(1, fib(5), add3(1, 1, 1)) * 2);
+            output(nothing());
+            return;
+            output(42);
+        ";
+        assert_eq!(run(source, vec![15]).await, [610, 3628800, 18, 0]);
+    }
+
+    #[crate::runtime::test]
+    async fn short_circuit() {
+        let source = "
+            fn side(v) { output(v); return v; }
+            output(side(0) && side(1));
+            output(side(2) && side(3));
+            output(side(4) || side(5));
+            output(side(0) || side(0));
+        ";
+        assert_eq!(run(source, vec![]).await, [0, 0, 2, 3, 1, 4, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| compile::<i64>(source).unwrap_err().to_string();
+        assert_eq!(error("let a = 1;\nb = 2;"), "2:1: Undefined variable 'b'");
+        assert_eq!(
+            error("output(1, 2);"),
+            "1:1: Function 'output' takes 1 arguments but 2 were given"
+        );
+        assert_eq!(error("foo();"), "1:1: Undefined function 'foo'");
+        assert_eq!(
+            error("fn f() {}\n  fn f() {}"),
+            "2:3: Function 'f' is already defined"
+        );
+        assert_eq!(error("let a = (1;"), "1:11: Expected ')', found ';'");
+        assert_eq!(error("let a = 1 $ 2;"), "1:11: Unexpected character '$'");
+        assert_eq!(
+            error("let größe = 1 $ 2;"),
+            "1:15: Unexpected character '$'"
+        );
+        assert_eq!(
+            error("while 1 {"),
+            "2:1: Expected '}', found 'end of input'"
+        );
+        assert_eq!(
+            error("let a = 1;\nfn f(a, b, a) {}"),
+            "2:1: Parameter 'a' of function 'f' is defined twice"
+        );
+        assert_eq!(
+            compile::<i32>("let a = 1;\noutput(a + 3000000000);")
+                .unwrap_err()
+                .to_string(),
+            "2:12: Value 3000000000 doesn't fit into a word"
+        );
+    }
+
+    #[crate::runtime::test]
+    async fn recursion_depth() {
+        let source = "
+            fn depth(n) { if n == 0 { return 0; } return depth(n - 1) + 1; }
+            output(depth(input()));
+        ";
+        assert_eq!(run(source, vec![100]).await, [100]);
+    }
+
+    #[crate::runtime::test]
+    async fn stack_overflow() {
+        let source = "
+            fn depth(n) { if n == 0 { return 0; } return depth(n - 1) + 1; }
+            output(depth(input()));
+        ";
+        let mut vm = Vm::new(compile(source).unwrap());
+        vm.input(stream::once(STACK_SIZE as i64));
+        assert!(matches!(
+            vm.run_and_collect().await,
+            Err(Error::InvalidAddress { .. })
+        ));
+    }
+}
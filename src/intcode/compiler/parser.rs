@@ -0,0 +1,367 @@
+//! Advent of Code 2019: Intcode compiler parser
+
+use super::lexer::Token;
+use super::{CompileError, Pos};
+
+/// Unary operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum UnaryOp {
+    /// Negation (`-a`)
+    Neg,
+    /// Logical not (`!a`)
+    Not,
+}
+
+/// Binary operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BinaryOp {
+    /// Addition (`a + b`)
+    Add,
+    /// Subtraction (`a - b`)
+    Sub,
+    /// Multiplication (`a * b`)
+    Mul,
+    /// Less than (`a < b`)
+    Lt,
+    /// Greater than (`a > b`)
+    Gt,
+    /// Less than or equal (`a <= b`)
+    Le,
+    /// Greater than or equal (`a >= b`)
+    Ge,
+    /// Equal (`a == b`)
+    Eq,
+    /// Not equal (`a != b`)
+    Ne,
+    /// Logical and, short-circuiting (`a && b`)
+    And,
+    /// Logical or, short-circuiting (`a || b`)
+    Or,
+}
+
+/// Expression
+#[derive(Debug)]
+pub(super) enum Expr {
+    /// Integer literal
+    Number(i64, Pos),
+    /// Value of a variable
+    Var(String, Pos),
+    /// Function call (including builtin functions `input` and `output`)
+    Call(String, Vec<Expr>, Pos),
+    /// Unary operation
+    Unary(UnaryOp, Box<Expr>),
+    /// Binary operation
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// Statement
+#[derive(Debug)]
+pub(super) enum Stmt {
+    /// Variable declaration (`let name = expr;`)
+    Let(String, Expr, Pos),
+    /// Assignment (`name = expr;`)
+    Assign(String, Expr, Pos),
+    /// Conditional (`if cond { ... } else { ... }`)
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    /// Loop (`while cond { ... }`)
+    While(Expr, Vec<Stmt>),
+    /// Return from function (`return expr;`)
+    Return(Option<Expr>),
+    /// Expression evaluated for its side effects (`expr;`)
+    Expr(Expr),
+}
+
+/// Function definition
+#[derive(Debug)]
+pub(super) struct Function {
+    /// Function name
+    pub name: String,
+    /// Parameter names
+    pub params: Vec<String>,
+    /// Function body
+    pub body: Vec<Stmt>,
+    /// Position of the definition
+    pub pos: Pos,
+}
+
+/// Parsed program
+#[derive(Debug)]
+pub(super) struct Program {
+    /// Function definitions
+    pub functions: Vec<Function>,
+    /// Top level statements (the main program)
+    pub main: Vec<Stmt>,
+}
+
+/// Recursive descent parser
+#[derive(Debug)]
+pub(super) struct Parser {
+    /// Tokens with their positions (ending with `Token::Eof`)
+    tokens: Vec<(Token, Pos)>,
+    /// Index of the current token
+    idx: usize,
+}
+
+impl Parser {
+    /// Create new parser for the given tokens
+    pub fn new(tokens: Vec<(Token, Pos)>) -> Self {
+        Self { tokens, idx: 0 }
+    }
+
+    /// Parse program (function definitions and top level statements)
+    pub fn program(mut self) -> Result<Program, CompileError> {
+        let mut functions = Vec::new();
+        let mut main = Vec::new();
+        while self.peek() != &Token::Eof {
+            if self.peek() == &Token::Fn {
+                functions.push(self.function()?);
+            } else {
+                main.push(self.statement()?);
+            }
+        }
+        Ok(Program { functions, main })
+    }
+
+    /// Current token
+    fn peek(&self) -> &Token {
+        &self.tokens[self.idx].0
+    }
+
+    /// Position of current token
+    fn pos(&self) -> Pos {
+        self.tokens[self.idx].1
+    }
+
+    /// Consume and return current token
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.idx].0.clone();
+        if token != Token::Eof {
+            self.idx += 1;
+        }
+        token
+    }
+
+    /// Error about the current token being unexpected
+    fn unexpected(&self, expected: &str) -> CompileError {
+        CompileError::new(
+            self.pos(),
+            format!("Expected {}, found '{}'", expected, self.peek()),
+        )
+    }
+
+    /// Consume the given symbol if it is the current token
+    fn accept(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Token::Symbol(s) if *s == symbol) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the given symbol or fail
+    fn expect(&mut self, symbol: &str) -> Result<(), CompileError> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", symbol)))
+        }
+    }
+
+    /// Consume an identifier or fail
+    fn ident(&mut self) -> Result<(String, Pos), CompileError> {
+        let pos = self.pos();
+        match self.peek() {
+            Token::Ident(_) => match self.next() {
+                Token::Ident(name) => Ok((name, pos)),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    /// Parse function definition
+    fn function(&mut self) -> Result<Function, CompileError> {
+        let pos = self.pos();
+        self.next();
+        let (name, _) = self.ident()?;
+        self.expect("(")?;
+        let mut params = Vec::new();
+        if !self.accept(")") {
+            loop {
+                params.push(self.ident()?.0);
+                if self.accept(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        let body = self.block()?;
+        Ok(Function {
+            name,
+            params,
+            body,
+            pos,
+        })
+    }
+
+    /// Parse block of statements in braces
+    fn block(&mut self) -> Result<Vec<Stmt>, CompileError> {
+        self.expect("{")?;
+        let mut stmts = Vec::new();
+        while !self.accept("}") {
+            if self.peek() == &Token::Eof {
+                return Err(self.unexpected("'}'"));
+            }
+            stmts.push(self.statement()?);
+        }
+        Ok(stmts)
+    }
+
+    /// Parse statement
+    fn statement(&mut self) -> Result<Stmt, CompileError> {
+        match self.peek() {
+            Token::Let => {
+                self.next();
+                let (name, pos) = self.ident()?;
+                self.expect("=")?;
+                let expr = self.expr()?;
+                self.expect(";")?;
+                Ok(Stmt::Let(name, expr, pos))
+            }
+            Token::If => {
+                self.next();
+                let cond = self.expr()?;
+                let then = self.block()?;
+                let otherwise = if self.peek() == &Token::Else {
+                    self.next();
+                    if self.peek() == &Token::If {
+                        vec![self.statement()?]
+                    } else {
+                        self.block()?
+                    }
+                } else {
+                    Vec::new()
+                };
+                Ok(Stmt::If(cond, then, otherwise))
+            }
+            Token::While => {
+                self.next();
+                let cond = self.expr()?;
+                let body = self.block()?;
+                Ok(Stmt::While(cond, body))
+            }
+            Token::Return => {
+                self.next();
+                let expr = if self.accept(";") {
+                    None
+                } else {
+                    let expr = self.expr()?;
+                    self.expect(";")?;
+                    Some(expr)
+                };
+                Ok(Stmt::Return(expr))
+            }
+            Token::Ident(_) if self.tokens[self.idx + 1].0 == Token::Symbol("=") => {
+                let (name, pos) = self.ident()?;
+                self.next();
+                let expr = self.expr()?;
+                self.expect(";")?;
+                Ok(Stmt::Assign(name, expr, pos))
+            }
+            _ => {
+                let expr = self.expr()?;
+                self.expect(";")?;
+                Ok(Stmt::Expr(expr))
+            }
+        }
+    }
+
+    /// Parse expression
+    fn expr(&mut self) -> Result<Expr, CompileError> {
+        self.binary(0)
+    }
+
+    /// Parse binary operations with at least the given precedence level
+    fn binary(&mut self, level: usize) -> Result<Expr, CompileError> {
+        /// Binary operators by increasing precedence level
+        const LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[
+                ("==", BinaryOp::Eq),
+                ("!=", BinaryOp::Ne),
+                ("<=", BinaryOp::Le),
+                (">=", BinaryOp::Ge),
+                ("<", BinaryOp::Lt),
+                (">", BinaryOp::Gt),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+            &[("*", BinaryOp::Mul)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for (symbol, op) in LEVELS[level] {
+                if self.accept(symbol) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    /// Parse unary operation
+    fn unary(&mut self) -> Result<Expr, CompileError> {
+        if self.accept("-") {
+            Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.unary()?)))
+        } else if self.accept("!") {
+            Ok(Expr::Unary(UnaryOp::Not, Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    /// Parse primary expression (literal, variable, call or parenthesized expression)
+    fn primary(&mut self) -> Result<Expr, CompileError> {
+        match self.peek() {
+            Token::Number(_) => {
+                let pos = self.pos();
+                match self.next() {
+                    Token::Number(n) => Ok(Expr::Number(n, pos)),
+                    _ => unreachable!(),
+                }
+            }
+            Token::Ident(_) => {
+                let (name, pos) = self.ident()?;
+                if self.accept("(") {
+                    let mut args = Vec::new();
+                    if !self.accept(")") {
+                        loop {
+                            args.push(self.expr()?);
+                            if self.accept(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    Ok(Expr::Call(name, args, pos))
+                } else {
+                    Ok(Expr::Var(name, pos))
+                }
+            }
+            Token::Symbol("(") => {
+                self.next();
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            _ => Err(self.unexpected("expression")),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Advent of Code 2019: Intcode data flow tracking
+
+use intcode_core::{Address, Instruction, Memory, Param, Value, Word};
+use std::collections::{BTreeSet, HashMap};
+
+/// Output value and the inputs that influenced it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFlow<V = Value> {
+    /// Number of steps executed before the output instruction
+    pub step: usize,
+    /// Address of the output instruction
+    pub ip: Address,
+    /// Value written
+    pub value: V,
+    /// Indexes of input values that influenced the output value
+    pub inputs: BTreeSet<usize>,
+}
+
+/// Branch decision and the inputs that influenced it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchFlow {
+    /// Number of steps executed before the jump instruction
+    pub step: usize,
+    /// Address of the jump instruction
+    pub ip: Address,
+    /// Flag to signal that the jump was taken
+    pub taken: bool,
+    /// Indexes of input values that influenced the condition or jump target
+    pub inputs: BTreeSet<usize>,
+}
+
+/// Data flow of input values through a program (see `Vm::track_data_flow`)
+///
+/// Every input value is tagged with its index (counting from 0). Tags are propagated through
+/// arithmetic and comparison instructions into the values they store, so for every value in
+/// memory it's known which input values influenced it. Stores of untagged values clear the tags
+/// of the memory location. Memory writes of extension instructions are not tracked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataFlow<V = Value> {
+    /// Tags of memory locations (locations without tags are omitted)
+    tags: HashMap<Address, BTreeSet<usize>>,
+    /// Number of input values read
+    inputs: usize,
+    /// All output values
+    outputs: Vec<OutputFlow<V>>,
+    /// Branch decisions that were influenced by input values
+    branches: Vec<BranchFlow>,
+}
+
+impl<V> Default for DataFlow<V> {
+    fn default() -> Self {
+        Self {
+            tags: HashMap::new(),
+            inputs: 0,
+            outputs: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+}
+
+impl<V: Word> DataFlow<V> {
+    /// Return the number of input values read
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// Return indexes of input values that influenced the value at the given address
+    pub fn inputs_at(&self, addr: Address) -> BTreeSet<usize> {
+        self.tags.get(&addr).cloned().unwrap_or_default()
+    }
+
+    /// Return all output values with the inputs that influenced them
+    pub fn outputs(&self) -> &[OutputFlow<V>] {
+        &self.outputs
+    }
+
+    /// Return all branch decisions that were influenced by input values
+    pub fn branches(&self) -> &[BranchFlow] {
+        &self.branches
+    }
+
+    /// Return tags of the value of the given parameter
+    fn param_tags(&self, param: &Param<V>, relative_base: Address) -> BTreeSet<usize> {
+        param
+            .address_with(relative_base)
+            .map(|addr| self.inputs_at(addr))
+            .unwrap_or_default()
+    }
+
+    /// Compute the change of the data flow by the given instruction (before it is executed)
+    ///
+    /// The change is applied with `apply` only after the instruction executed successfully.
+    pub(super) fn change(
+        &self,
+        instruction: &Instruction<V>,
+        memory: &Memory<V>,
+        ip: Address,
+        relative_base: Address,
+        step: usize,
+    ) -> FlowChange<V> {
+        let mut change = FlowChange {
+            tags: None,
+            input: false,
+            output: None,
+            branch: None,
+        };
+        match instruction {
+            Instruction::Add(p1, p2, p3)
+            | Instruction::Multiply(p1, p2, p3)
+            | Instruction::LessThan(p1, p2, p3)
+            | Instruction::Equals(p1, p2, p3) => {
+                let mut tags = self.param_tags(p1, relative_base);
+                tags.extend(self.param_tags(p2, relative_base));
+                change.tags = p3.address_with(relative_base).map(|addr| (addr, tags));
+            }
+            Instruction::Input(p1) => {
+                let tags = BTreeSet::from([self.inputs]);
+                change.tags = p1.address_with(relative_base).map(|addr| (addr, tags));
+                change.input = true;
+            }
+            Instruction::Output(p1) => {
+                change.output = Some(OutputFlow {
+                    step,
+                    ip,
+                    value: p1.fetch(memory, relative_base),
+                    inputs: self.param_tags(p1, relative_base),
+                });
+            }
+            Instruction::JumpIfNotZero(p1, p2) | Instruction::JumpIfZero(p1, p2) => {
+                let mut inputs = self.param_tags(p1, relative_base);
+                inputs.extend(self.param_tags(p2, relative_base));
+                if !inputs.is_empty() {
+                    let zero = p1.fetch(memory, relative_base) == V::from(0);
+                    let taken = zero == matches!(instruction, Instruction::JumpIfZero(_, _));
+                    change.branch = Some(BranchFlow {
+                        step,
+                        ip,
+                        taken,
+                        inputs,
+                    });
+                }
+            }
+            Instruction::AdjustRelativeBase(_)
+            | Instruction::Done
+            | Instruction::Extension(_, _) => (),
+        }
+        change
+    }
+
+    /// Apply the given change and return what is needed to revert it
+    pub(super) fn apply(&mut self, change: FlowChange<V>) -> FlowUndo {
+        let tags = change
+            .tags
+            .map(|(addr, tags)| (addr, self.set_tags(addr, tags)));
+        if change.input {
+            self.inputs += 1;
+        }
+        let undo = FlowUndo {
+            tags,
+            input: change.input,
+            output: change.output.is_some(),
+            branch: change.branch.is_some(),
+        };
+        self.outputs.extend(change.output);
+        self.branches.extend(change.branch);
+        undo
+    }
+
+    /// Revert a change applied before (changes are reverted in reverse order)
+    pub(super) fn revert(&mut self, undo: FlowUndo) {
+        if let Some((addr, tags)) = undo.tags {
+            self.set_tags(addr, tags);
+        }
+        if undo.input {
+            self.inputs = self.inputs.saturating_sub(1);
+        }
+        if undo.output {
+            self.outputs.pop();
+        }
+        if undo.branch {
+            self.branches.pop();
+        }
+    }
+
+    /// Set tags of the given location and return its previous tags
+    fn set_tags(&mut self, addr: Address, tags: BTreeSet<usize>) -> BTreeSet<usize> {
+        let previous = if tags.is_empty() {
+            self.tags.remove(&addr)
+        } else {
+            self.tags.insert(addr, tags)
+        };
+        previous.unwrap_or_default()
+    }
+}
+
+/// Change of the data flow by one step (see `DataFlow::change`)
+#[derive(Debug)]
+pub(super) struct FlowChange<V> {
+    /// Address and new tags of the location written
+    tags: Option<(Address, BTreeSet<usize>)>,
+    /// Flag to signal that an input value is read
+    input: bool,
+    /// Output value written
+    output: Option<OutputFlow<V>>,
+    /// Branch decision influenced by input values
+    branch: Option<BranchFlow>,
+}
+
+/// State needed to revert an applied data flow change (see `DataFlow::apply`)
+#[derive(Debug)]
+pub(super) struct FlowUndo {
+    /// Address and previous tags of the location written
+    tags: Option<(Address, BTreeSet<usize>)>,
+    /// Flag to signal that an input value was read
+    input: bool,
+    /// Flag to signal that an output value was added
+    output: bool,
+    /// Flag to signal that a branch decision was added
+    branch: bool,
+}
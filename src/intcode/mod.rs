@@ -1,101 +1,135 @@
 //! Advent of Code 2019: Intcode
 
-mod memory;
-pub use self::memory::{Address, Memory, Value};
+pub use intcode_core::{
+    Address, Arithmetic, BinaryError, Context, Effect, Error, Extension, Instruction, Machine,
+    Memory, Param, ParseMemoryError, Value, Word,
+};
+
+mod blocking;
+pub use self::blocking::BlockingVm;
+
+mod cancel;
+pub use self::cancel::CancellationToken;
+
+mod compiler;
+pub use self::compiler::{compile, CompileError, Pos};
+
+mod flow;
+pub use self::flow::{BranchFlow, DataFlow, OutputFlow};
+
+mod report;
+pub use self::report::Report;
+
+mod scheduler;
+pub use self::scheduler::{ScheduleError, Scheduler};
+
+mod sender;
+pub use self::sender::InputSender;
+
+mod session;
+pub use self::session::{Event, ParseSessionError, Session};
+
+mod state;
+pub use self::state::State;
+
+mod undo;
 
 mod vm;
-pub use self::vm::Vm;
+pub use self::vm::{InputError, Vm};
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use async_std::stream;
+    use async_std::prelude::*;
+    use async_std::{stream, task};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day02_example_1() {
         let program = Memory::from(vec![1, 0, 0, 0, 99]);
         let mut vm = Vm::new(program);
-        vm.run().await;
+        vm.run().await.unwrap();
         assert_eq!(vm.memory(), &[2, 0, 0, 0, 99]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day02_example_2() {
         let program = Memory::from(vec![2, 3, 0, 3, 99]);
         let mut vm = Vm::new(program);
-        vm.run().await;
+        vm.run().await.unwrap();
         assert_eq!(vm.memory(), &[2, 3, 0, 6, 99]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day02_example_3() {
         let program = Memory::from(vec![2, 4, 4, 5, 99, 0]);
         let mut vm = Vm::new(program);
-        vm.run().await;
+        vm.run().await.unwrap();
         assert_eq!(vm.memory(), &[2, 4, 4, 5, 99, 9801]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day02_example_4() {
         let program = Memory::from(vec![1, 1, 1, 4, 99, 5, 6, 0, 99]);
         let mut vm = Vm::new(program);
-        vm.run().await;
+        vm.run().await.unwrap();
         assert_eq!(vm.memory(), &[30, 1, 1, 4, 2, 5, 6, 0, 99]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day05_position_mode_equals() {
         let program = Memory::from(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![5]));
-        assert_eq!(vm.run_and_collect().await, &[0]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[0]);
 
         let mut vm = Vm::new(program);
         vm.input(stream::from_iter(vec![8]));
-        assert_eq!(vm.run_and_collect().await, &[1]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day05_position_mode_less_than() {
         let program = Memory::from(vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8]);
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![5]));
-        assert_eq!(vm.run_and_collect().await, &[1]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
 
         let mut vm = Vm::new(program);
         vm.input(stream::from_iter(vec![8]));
-        assert_eq!(vm.run_and_collect().await, &[0]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[0]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day05_immediate_mode_equals() {
         let program = Memory::from(vec![3, 3, 1108, -1, 8, 3, 4, 3, 99]);
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![5]));
-        assert_eq!(vm.run_and_collect().await, &[0]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[0]);
 
         let mut vm = Vm::new(program);
         vm.input(stream::from_iter(vec![8]));
-        assert_eq!(vm.run_and_collect().await, &[1]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day05_immediate_mode_less_than() {
         let program = Memory::from(vec![3, 3, 1107, -1, 8, 3, 4, 3, 99]);
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![5]));
-        assert_eq!(vm.run_and_collect().await, &[1]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
 
         let mut vm = Vm::new(program);
         vm.input(stream::from_iter(vec![8]));
-        assert_eq!(vm.run_and_collect().await, &[0]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[0]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day05_position_mode_jump() {
         let program = Memory::from(vec![
             3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9,
@@ -103,27 +137,27 @@ mod tests {
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![0]));
-        assert_eq!(vm.run_and_collect().await, &[0]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[0]);
 
         let mut vm = Vm::new(program);
         vm.input(stream::from_iter(vec![1]));
-        assert_eq!(vm.run_and_collect().await, &[1]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day05_immediate_mode_jump() {
         let program = Memory::from(vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1]);
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![0]));
-        assert_eq!(vm.run_and_collect().await, &[0]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[0]);
 
         let mut vm = Vm::new(program);
         vm.input(stream::from_iter(vec![1]));
-        assert_eq!(vm.run_and_collect().await, &[1]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
     }
 
-    #[async_std::test]
+    #[crate::runtime::test]
     async fn day05_large_example() {
         let program = Memory::from(vec![
             3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
@@ -133,14 +167,746 @@ mod tests {
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![5]));
-        assert_eq!(vm.run_and_collect().await, &[999]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[999]);
 
         let mut vm = Vm::new(program.clone());
         vm.input(stream::from_iter(vec![8]));
-        assert_eq!(vm.run_and_collect().await, &[1000]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1000]);
 
         let mut vm = Vm::new(program);
         vm.input(stream::from_iter(vec![11]));
-        assert_eq!(vm.run_and_collect().await, &[1001]);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1001]);
+    }
+
+    #[crate::runtime::test]
+    async fn arithmetic_checked() {
+        let program = Memory::from(vec![1002, 5, 3, 5, 99, 1_000_000_000]);
+        let mut vm = Vm::new(program);
+        assert!(matches!(vm.run().await, Err(Error::Overflow { ip: 0 })));
+    }
+
+    #[crate::runtime::test]
+    async fn arithmetic_wrapping() {
+        let program = Memory::from(vec![1002, 5, 3, 5, 99, 1_000_000_000]);
+        let mut vm = Vm::new(program);
+        vm.arithmetic(Arithmetic::Wrapping).run().await.unwrap();
+        assert_eq!(vm.memory().get(5), -1_294_967_296);
+    }
+
+    #[crate::runtime::test]
+    async fn arithmetic_saturating() {
+        let program = Memory::from(vec![1002, 5, 3, 5, 99, 1_000_000_000]);
+        let mut vm = Vm::new(program);
+        vm.arithmetic(Arithmetic::Saturating).run().await.unwrap();
+        assert_eq!(vm.memory().get(5), i32::MAX);
+    }
+
+    #[crate::runtime::test]
+    async fn words_i64() {
+        let program = Memory::<i64>::from(vec![1002, 5, 3, 5, 99, 1_000_000_000]);
+        let mut vm = Vm::new(program);
+        vm.run().await.unwrap();
+        assert_eq!(vm.memory().get(5), 3_000_000_000);
+    }
+
+    #[cfg(feature = "bigint")]
+    #[crate::runtime::test]
+    async fn words_bigint() {
+        use num_bigint::BigInt;
+
+        let program = Memory::from(vec![
+            BigInt::from(1002),
+            BigInt::from(5),
+            BigInt::from(3),
+            BigInt::from(5),
+            BigInt::from(99),
+            BigInt::from(i128::MAX),
+        ]);
+        let mut vm = Vm::new(program);
+        vm.run().await.unwrap();
+        assert_eq!(vm.memory().get(5), BigInt::from(i128::MAX) * 3);
+    }
+
+    #[crate::runtime::test]
+    async fn timeout_waiting_for_input() {
+        let program = Memory::from(vec![1101, 1, 2, 0, 3, 0, 99]);
+        let mut vm = Vm::new(program);
+        vm.input(stream::pending());
+        let token = CancellationToken::with_timeout(Duration::from_millis(10));
+        assert!(matches!(vm.run_with(&token).await, Err(Error::TimedOut)));
+        assert_eq!(vm.ip(), 4);
+        assert_eq!(vm.result(), 3);
+        assert!(!vm.is_done());
+    }
+
+    #[crate::runtime::test]
+    async fn timeout_endless_loop() {
+        let program = Memory::from(vec![1105, 1, 0]);
+        let mut vm = Vm::new(program);
+        let token = CancellationToken::with_timeout(Duration::from_millis(10));
+        assert!(matches!(vm.run_with(&token).await, Err(Error::TimedOut)));
+        assert_eq!(vm.ip(), 0);
+    }
+
+    #[crate::runtime::test]
+    async fn cancel_and_resume() {
+        let program = Memory::from(vec![3, 0, 4, 0, 99]);
+        let mut vm = Vm::new(program);
+        let (tx, rx) = async_std::channel::unbounded();
+        vm.input(rx);
+        let output = vm.output();
+
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        task::spawn(async move { canceller.cancel() });
+        assert!(matches!(vm.run_with(&token).await, Err(Error::Cancelled)));
+        assert!(token.is_cancelled());
+        assert_eq!(vm.ip(), 0);
+
+        tx.send(42).await.unwrap();
+        let (result, output) = vm.run().join(output.collect::<Vec<_>>()).await;
+        result.unwrap();
+        assert_eq!(output, &[42]);
+    }
+
+    #[crate::runtime::test]
+    async fn fallible_input() {
+        use std::error::Error as _;
+
+        // Output the sum of two input values
+        let program = Memory::from(vec![3, 11, 3, 12, 1, 11, 12, 11, 4, 11, 99, 0, 0]);
+        let mut vm = Vm::new(program);
+        let error = std::io::Error::new(std::io::ErrorKind::InvalidData, "bad value");
+        vm.try_input(stream::from_iter(vec![Ok(1), Err(error)]));
+        let output = vm.output();
+        let (result, _) = vm.run().join(output.collect::<Vec<_>>()).await;
+        match &result {
+            Err(Error::Input { ip: 2, source }) => assert_eq!(source.to_string(), "bad value"),
+            result => panic!("Unexpected result {:?}", result),
+        }
+        let error = result.unwrap_err();
+        let source = error.source().unwrap().downcast_ref::<std::io::Error>();
+        assert_eq!(source.unwrap().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(vm.ip(), 2);
+        assert!(!vm.is_done());
+
+        // The end of the input stream fails the same way
+        vm.input(stream::empty());
+        assert!(matches!(vm.run().await, Err(Error::Input { ip: 2, .. })));
+
+        // Running again without new input fails instead of waiting for the closed input
+        let error = vm.run().await.unwrap_err();
+        assert!(matches!(error, Error::Input { ip: 2, .. }));
+        assert_eq!(error.source().unwrap().to_string(), "No input stream set");
+
+        // Running again without new output fails at the output instruction
+        vm.try_input(stream::once(Ok::<_, std::io::Error>(2)));
+        assert!(matches!(vm.run().await, Err(Error::Output { ip: 8 })));
+
+        // Resume with new output
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[3]);
+    }
+
+    #[crate::runtime::test]
+    async fn input_sender() {
+        // Echo input values until a zero is read
+        let program = Memory::from(vec![3, 9, 4, 9, 1005, 9, 0, 99, 0, 0]);
+        let mut vm = Vm::new(program.clone());
+        let input = vm.input_sender();
+        let mut output = vm.output();
+        input.send(1).unwrap();
+        let driver = async {
+            let mut values = Vec::new();
+            while let Some(value) = output.next().await {
+                values.push(value);
+                // Send double the previous output value, then zero to stop the program
+                let _ = input.send(if value < 8 { value * 2 } else { 0 });
+            }
+            values
+        };
+        let (result, values) = vm.run().join(driver).await;
+        assert_eq!(result.unwrap().inputs, 5);
+        assert_eq!(values, &[1, 2, 4, 8, 0]);
+        assert!(input.is_closed());
+        assert_eq!(input.send(1), Err(1));
+
+        let mut vm = Vm::new(program);
+        let input = vm.input_sender();
+        input.send(3).unwrap();
+        assert!(input.close());
+        let output = vm.output();
+        let (result, values) = vm.run().join(output.collect::<Vec<_>>()).await;
+        assert!(matches!(result, Err(Error::Input { ip: 0, .. })));
+        assert_eq!(values, &[3]);
+    }
+
+    #[crate::runtime::test]
+    async fn spawn_and_join() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Vm>();
+
+        let program = Memory::from(vec![3, 0, 102, 2, 0, 0, 4, 0, 99]);
+        let mut vm = Vm::new(program);
+        vm.input(stream::once(21));
+        let output = vm.output();
+        let (vm, result) = vm.spawn().await;
+        assert_eq!(result.unwrap().steps, 4);
+        assert!(vm.is_done());
+        assert_eq!(output.collect::<Vec<_>>().await, &[42]);
+    }
+
+    #[crate::runtime::test]
+    async fn run_all() {
+        let program = Memory::from(vec![3, 0, 1001, 0, 1, 0, 4, 0, 99]);
+        let mut vms = vec![Vm::new(program.clone()), Vm::new(program)];
+        let output = vms[0].output();
+        vms[1].input(output);
+        vms[0].input(stream::once(1));
+        let output = vms[1].output();
+        let token = CancellationToken::new();
+        let reports = Vm::run_all(&mut vms, &token).await.unwrap();
+        assert_eq!(reports.len(), 2);
+        assert!(vms.iter().all(|vm| vm.is_done()));
+        assert_eq!(output.collect::<Vec<_>>().await, &[3]);
+
+        // Failing vm cancels the vm waiting for its output
+        let mut vms = vec![
+            Vm::new(Memory::from(vec![42])),
+            Vm::new(Memory::from(vec![3, 0, 99])),
+        ];
+        let output = vms[0].output();
+        vms[1].input(output.chain(stream::pending()));
+        assert!(matches!(
+            Vm::run_all(&mut vms, &token).await,
+            Err(Error::UnknownOpcode { ip: 0, opcode: 42 })
+        ));
+        assert_eq!(vms.len(), 2);
+        assert_eq!(vms[1].ip(), 0);
+
+        // Failing vm fails the vm sending to it with its own error
+        let mut vms = vec![
+            Vm::new(Memory::from(vec![104, 1, 1105, 1, 0])),
+            Vm::new(Memory::from(vec![42])),
+        ];
+        let output = vms[0].output();
+        vms[1].input(output);
+        assert!(matches!(
+            Vm::run_all(&mut vms, &token).await,
+            Err(Error::UnknownOpcode { ip: 0, opcode: 42 })
+        ));
+        assert_eq!(vms.len(), 2);
+        assert_eq!(vms[0].ip(), 0);
+
+        // Dropping the future cancels all vms
+        let mut vms = vec![Vm::new(Memory::from(vec![3, 0, 99]))];
+        let (tx, rx) = async_std::channel::unbounded::<Value>();
+        vms[0].input(rx);
+        let outer = CancellationToken::with_timeout(Duration::from_millis(10));
+        assert!(matches!(
+            outer.run(Vm::run_all(&mut vms, &token)).await,
+            Err(Error::TimedOut)
+        ));
+        assert!(vms.is_empty());
+        for _ in 0..1000 {
+            if tx.is_closed() {
+                break;
+            }
+            task::sleep(Duration::from_millis(1)).await;
+        }
+        assert!(tx.is_closed());
+    }
+
+    #[test]
+    fn blocking_vm() {
+        // Double every input value until a zero is read
+        let program = Memory::from(vec![
+            3, 15, 1006, 15, 14, 102, 2, 15, 15, 4, 15, 1105, 1, 0, 99, 0,
+        ]);
+        let vm = BlockingVm::spawn(Vm::new(program.clone()));
+        vm.send_input(21).unwrap();
+        assert_eq!(vm.recv_output(), Some(42));
+        vm.send_input(0).unwrap();
+        assert_eq!(vm.recv_output(), None);
+        let (vm, result) = vm.join();
+        assert!(result.is_ok());
+        assert!(vm.is_done());
+
+        // Dropping the handle cancels a vm waiting for input
+        let vm = BlockingVm::spawn(Vm::new(program));
+        drop(vm);
+    }
+
+    #[crate::runtime::test]
+    async fn scheduler() {
+        // Add 1 to every input value and output it, until a value of 10 or more is output
+        let program = Memory::from(vec![
+            3, 16, 1001, 16, 1, 16, 4, 16, 1007, 16, 10, 17, 1005, 17, 0, 99, 0, 0,
+        ]);
+        let mut scheduler = Scheduler::new();
+        let a = scheduler.add(Vm::new(program.clone()));
+        let b = scheduler.add(Vm::new(program));
+        let c = scheduler.add(Vm::new(Memory::from(vec![3, 0, 4, 0, 99])));
+        scheduler.connect(a, b).connect(b, a).send(a, 0).send(c, 7);
+        assert_eq!(scheduler.run().await.unwrap(), &[7]);
+        assert_eq!(scheduler.vm(a).memory().get(16), 11);
+        assert_eq!(scheduler.vm(b).memory().get(16), 10);
+        assert!(scheduler.into_vms().iter().all(Vm::is_done));
+    }
+
+    #[crate::runtime::test]
+    async fn scheduler_deadlock() {
+        // Echo input values forever, but nobody sends the first value
+        let program = Memory::from(vec![3, 6, 4, 6, 1105, 1, 0]);
+        let mut scheduler = Scheduler::new();
+        let a = scheduler.add(Vm::new(program.clone()));
+        let b = scheduler.add(Vm::new(program));
+        let c = scheduler.add(Vm::new(Memory::from(vec![99])));
+        scheduler.connect(a, b).connect(b, a);
+        match scheduler.run().await {
+            Err(err @ ScheduleError::Deadlock { .. }) => {
+                assert_eq!(
+                    err.to_string(),
+                    "Deadlock: vm 0 waits for input at address 0, vm 1 waits for input at \
+                     address 0, vm 2 halted at address 0"
+                );
+                if let ScheduleError::Deadlock { states } = err {
+                    assert_eq!(states.len(), 3);
+                    assert!(states[c].done);
+                }
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[crate::runtime::test]
+    async fn scheduler_pending_input() {
+        // Input values of a replayed session or taken back by stepping back aren't a deadlock
+        let program = Memory::from(vec![3, 0, 4, 0, 99]);
+        let mut replayed = Vm::new(program.clone());
+        replayed.replay("in 0 5\nout 1 5".parse().unwrap());
+        let mut rewound = Vm::new(program);
+        rewound.undo_log(10).input(stream::once(3));
+        rewound.step().await.unwrap();
+        assert!(rewound.step_back());
+        assert!(rewound.has_pending_input());
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(replayed);
+        scheduler.add(rewound);
+        assert_eq!(scheduler.run().await.unwrap(), &[5, 3]);
+    }
+
+    #[crate::runtime::test]
+    async fn reset() {
+        let program = Memory::from(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let mut vm = Vm::new(program.clone());
+        vm.input(stream::from_iter(vec![8]));
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
+        assert!(vm.is_done());
+
+        vm.reset();
+        assert_eq!(vm.memory(), &program);
+        assert_eq!(vm.ip(), 0);
+        assert!(!vm.is_done());
+
+        vm.input(stream::from_iter(vec![5]));
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[0]);
+    }
+
+    #[crate::runtime::test]
+    async fn record_and_replay() {
+        let program = Memory::from(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let mut vm = Vm::new(program.clone());
+        vm.record().input(stream::from_iter(vec![8]));
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
+        assert_eq!(vm.steps(), 4);
+        let session = vm.take_session().unwrap();
+        assert_eq!(session.to_string(), "in 0 8\nout 2 1\n");
+
+        let mut vm = Vm::new(program.clone());
+        vm.replay(session.clone());
+        vm.run().await.unwrap();
+        assert!(vm.is_done());
+
+        let mut vm = Vm::new(program);
+        vm.noun(8).replay(session);
+        assert_eq!(
+            vm.run().await.unwrap_err().to_string(),
+            "Replay mismatch at address 6: expected out 2 1, found out 2 0"
+        );
+    }
+
+    #[crate::runtime::test]
+    async fn replay_incomplete() {
+        let program = Memory::from(vec![4, 0, 99]);
+        let mut vm = Vm::new(program.clone());
+        vm.replay("out 0 4\nin 1 5".parse().unwrap());
+        assert_eq!(
+            vm.run().await.unwrap_err().to_string(),
+            "Replay mismatch at address 2: expected in 1 5, found halt at step 1"
+        );
+
+        let mut vm = Vm::new(program);
+        vm.replay("in 0 5".parse().unwrap());
+        assert_eq!(
+            vm.run().await.unwrap_err().to_string(),
+            "Replay mismatch at address 0: expected in 0 5, found out 0 4"
+        );
+    }
+
+    #[crate::runtime::test]
+    async fn record_and_replay_cancelled_output() {
+        use futures_util::FutureExt;
+
+        // Output a value twice, the second send is pending until the first value is received
+        let program = Memory::from(vec![4, 0, 4, 0, 99]);
+        let mut vm = Vm::new(program.clone());
+        vm.record();
+        let mut output = vm.output();
+        vm.step().await.unwrap();
+        assert!(vm.step().now_or_never().is_none());
+        assert_eq!(output.next().await, Some(4));
+        vm.run().await.unwrap();
+        let session = vm.take_session().unwrap();
+        assert_eq!(session.to_string(), "out 0 4\nout 1 4\n");
+
+        let mut vm = Vm::new(program);
+        vm.replay(session);
+        let mut output = vm.output();
+        vm.step().await.unwrap();
+        assert!(vm.step().now_or_never().is_none());
+        assert_eq!(output.next().await, Some(4));
+        vm.run().await.unwrap();
+        assert!(vm.is_done());
+    }
+
+    #[crate::runtime::test]
+    async fn run_report() {
+        let program = Memory::from(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let mut vm = Vm::new(program);
+        vm.input(stream::from_iter(vec![8]));
+        let output = vm.output();
+        let (report, output) = vm.run().join(output.collect::<Vec<_>>()).await;
+        let report = report.unwrap();
+        assert_eq!(output, &[1]);
+        assert_eq!(report.steps, 4);
+        assert_eq!(report.inputs, 1);
+        assert_eq!(report.outputs, 1);
+        assert_eq!(report.ip, 8);
+        assert_eq!(report.max_address, Some(10));
+
+        // Running again after the program is done executes nothing
+        let report = vm.run().await.unwrap();
+        assert_eq!(report.steps, 0);
+        assert_eq!(report.max_address, None);
+    }
+
+    #[crate::runtime::test]
+    async fn state_and_restore() {
+        let program = Memory::from(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let mut vm = Vm::new(program);
+        vm.input(stream::from_iter(vec![8]));
+        vm.step().await.unwrap();
+        let state = vm.state();
+        assert_eq!(state.ip, 2);
+        assert_eq!(state.steps, 1);
+        assert_eq!(state.memory.get(9), 8);
+
+        let mut vm2 = Vm::from(state.clone());
+        assert_eq!(vm2.run_and_collect().await.unwrap(), &[1]);
+        let mut state2 = state.clone();
+        state2.memory.set(9, 5);
+        vm2.restore(state2);
+        assert!(!vm2.is_done());
+        assert_eq!(vm2.run_and_collect().await.unwrap(), &[0]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let memory = Memory::from(vec![1002, 4, 3, 4, 33]);
+        let json = serde_json::to_string(&memory).unwrap();
+        assert_eq!(json, "[1002,4,3,4,33]");
+        assert_eq!(serde_json::from_str::<Memory>(&json).unwrap(), memory);
+
+        let state = Vm::new(memory).state();
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(
+            json,
+            r#"{"memory":[1002,4,3,4,33],"ip":0,"relative_base":0,"steps":0,"done":false}"#
+        );
+        assert_eq!(serde_json::from_str::<State>(&json).unwrap(), state);
+
+        let instruction =
+            Instruction::Add(Param::Position(4), Param::Immediate(-3), Param::Relative(2));
+        let json = serde_json::to_string(&instruction).unwrap();
+        assert_eq!(
+            json,
+            r#"{"Add":[{"Position":4},{"Immediate":-3},{"Relative":2}]}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Instruction<Value>>(&json).unwrap(),
+            instruction
+        );
+    }
+
+    #[crate::runtime::test]
+    async fn data_flow() {
+        // Outputs (a * 2) and (b + 7), then whether a < b, and jumps if b == 0
+        let program = Memory::from(vec![
+            3, 30, 3, 31, 1002, 30, 2, 32, 4, 32, 1001, 31, 7, 33, 4, 33, 7, 30, 31, 34, 4, 34,
+            1105, 1, 26, 99, 1006, 31, 29, 99, 0, 0, 0, 0, 0,
+        ]);
+        let mut vm = Vm::new(program);
+        vm.track_data_flow().input(stream::from_iter(vec![3, 0]));
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[6, 7, 0]);
+
+        let flow = vm.data_flow().unwrap();
+        assert_eq!(flow.inputs(), 2);
+        let outputs: Vec<_> = flow
+            .outputs()
+            .iter()
+            .map(|output| {
+                (
+                    output.ip,
+                    output.value,
+                    output.inputs.iter().copied().collect(),
+                )
+            })
+            .collect::<Vec<(_, _, Vec<_>)>>();
+        assert_eq!(
+            outputs,
+            &[(8, 6, vec![0]), (14, 7, vec![1]), (20, 0, vec![0, 1])]
+        );
+        // The unconditional jump isn't influenced by inputs
+        assert_eq!(flow.branches().len(), 1);
+        assert_eq!(flow.branches()[0].ip, 26);
+        assert!(flow.branches()[0].taken);
+        assert!(flow.branches()[0].inputs.iter().eq(&[1]));
+        assert!(flow.inputs_at(32).iter().eq(&[0]));
+        assert!(flow.inputs_at(0).is_empty());
+    }
+
+    #[crate::runtime::test]
+    async fn data_flow_failed_steps() {
+        use futures_util::FutureExt;
+
+        // The overflowing multiplication doesn't store a value, so tags of the input stay
+        let program = Memory::from(vec![3, 8, 1002, 9, 3, 8, 99, 0, 0, 1_000_000_000]);
+        let mut vm = Vm::new(program);
+        vm.track_data_flow().input(stream::once(1));
+        assert!(matches!(vm.run().await, Err(Error::Overflow { ip: 2 })));
+        assert!(vm.data_flow().unwrap().inputs_at(8).iter().eq(&[0]));
+
+        // Cancelled input and output steps are tracked once when they're executed again
+        let program = Memory::from(vec![3, 0, 4, 0, 4, 0, 99]);
+        let mut vm = Vm::new(program);
+        let (tx, rx) = async_std::channel::unbounded();
+        vm.track_data_flow().undo_log(10).input(rx);
+        let mut output = vm.output();
+        assert!(vm.step().now_or_never().is_none());
+        tx.send(5).await.unwrap();
+        vm.step().await.unwrap();
+        vm.step().await.unwrap();
+        assert!(vm.step().now_or_never().is_none());
+        assert_eq!(vm.data_flow().unwrap().inputs(), 1);
+        assert_eq!(vm.data_flow().unwrap().outputs().len(), 1);
+        assert_eq!(output.next().await, Some(5));
+        vm.run().await.unwrap();
+        assert_eq!(vm.data_flow().unwrap().outputs().len(), 2);
+
+        // Stepping back reverts the data flow
+        while vm.step_back() {}
+        let flow = vm.data_flow().unwrap();
+        assert_eq!(flow.inputs(), 0);
+        assert!(flow.outputs().is_empty());
+        assert!(flow.inputs_at(0).is_empty());
+    }
+
+    #[crate::runtime::test]
+    async fn step_back() {
+        let program = Memory::from(vec![1002, 4, 3, 4, 33]);
+        let mut vm = Vm::new(program.clone());
+        vm.undo_log(10).run().await.unwrap();
+        assert_eq!(vm.memory(), &[1002, 4, 3, 4, 99]);
+
+        assert!(vm.step_back());
+        assert!(!vm.is_done());
+        assert_eq!(vm.ip(), 4);
+        assert!(vm.step_back());
+        assert_eq!(vm.memory(), &program);
+        assert_eq!(vm.ip(), 0);
+        assert_eq!(vm.steps(), 0);
+        assert!(!vm.step_back());
+
+        vm.run().await.unwrap();
+        assert_eq!(vm.memory(), &[1002, 4, 3, 4, 99]);
+    }
+
+    #[crate::runtime::test]
+    async fn undo_log_capacity() {
+        let program = Memory::from(vec![1101, 1, 1, 0, 1101, 2, 2, 0, 99]);
+        let mut vm = Vm::new(program);
+        vm.undo_log(1).run().await.unwrap();
+        assert!(vm.step_back());
+        assert!(!vm.step_back());
+        assert_eq!(vm.ip(), 8);
+        assert_eq!(vm.memory().get(0), 4);
+    }
+
+    #[crate::runtime::test]
+    async fn run_back_and_rewind() {
+        let program = Memory::from(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let mut vm = Vm::new(program);
+        vm.undo_log(100).input(stream::from_iter(vec![8]));
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
+
+        assert!(vm.rewind_to_output());
+        assert_eq!(vm.ip(), 6);
+        assert!(vm.run_back_to_write(9));
+        assert_eq!(vm.ip(), 2);
+        assert_eq!(vm.memory().get(9), 8);
+        assert!(vm.run_back_to_write(9));
+        assert_eq!(vm.ip(), 0);
+        assert_eq!(vm.memory().get(9), -1);
+        assert!(!vm.rewind_to_output());
+
+        // The input value taken back is read again
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[1]);
+    }
+
+    #[crate::runtime::test]
+    async fn step_back_record_and_replay() {
+        let program = Memory::from(vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8]);
+        let mut vm = Vm::new(program.clone());
+        vm.undo_log(100).record().input(stream::from_iter(vec![8]));
+        let mut output = vm.output();
+        for _ in 0..3 {
+            vm.step().await.unwrap();
+        }
+        assert_eq!(output.next().await, Some(1));
+        assert!(vm.rewind_to_output());
+        assert_eq!(vm.session().unwrap().to_string(), "in 0 8\n");
+        assert!(vm.step_back());
+        assert!(vm.step_back());
+        assert_eq!(vm.session().unwrap().to_string(), "");
+        let (result, output) = vm.run().join(output.collect::<Vec<_>>()).await;
+        result.unwrap();
+        assert_eq!(output, &[1]);
+        let session = vm.take_session().unwrap();
+        assert_eq!(session.to_string(), "in 0 8\nout 2 1\n");
+
+        // Events taken back are replayed again
+        let mut vm = Vm::new(program);
+        vm.undo_log(100).replay(session);
+        for _ in 0..3 {
+            vm.step().await.unwrap();
+        }
+        assert!(vm.rewind_to_output());
+        vm.step().await.unwrap();
+        assert!(vm.run_back_to_write(9));
+        assert!(vm.run_back_to_write(9));
+        assert_eq!(vm.ip(), 0);
+        vm.run().await.unwrap();
+        assert!(vm.is_done());
+    }
+
+    /// Test extension that records values of its parameter
+    struct Record(Arc<Mutex<Vec<Value>>>);
+
+    impl Extension<Value> for Record {
+        fn params(&self) -> usize {
+            1
+        }
+
+        fn execute(&self, ctx: &mut Context<'_, Value>) -> Result<(), Error> {
+            self.0.lock().unwrap().push(ctx.fetch(0));
+            Ok(())
+        }
+    }
+
+    /// Test extension that decrements p1 and jumps to p2 unless p1 reached zero
+    struct Loop;
+
+    impl Extension<Value> for Loop {
+        fn params(&self) -> usize {
+            2
+        }
+
+        fn execute(&self, ctx: &mut Context<'_, Value>) -> Result<(), Error> {
+            let counter = ctx.fetch(0);
+            if counter <= 0 {
+                return Err(ctx.error("counter exhausted"));
+            }
+            ctx.store(0, counter - 1)?;
+            if counter > 1 {
+                let target = ctx.fetch_address(1)?;
+                ctx.jump(target);
+            }
+            Ok(())
+        }
+    }
+
+    #[crate::runtime::test]
+    async fn extension_params() {
+        let program = Memory::from(vec![150, 7, 50, 6, 99, 0, 42]);
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let mut vm = Vm::new(program);
+        vm.extension(50, Record(recorded.clone()));
+        vm.run().await.unwrap();
+        assert_eq!(*recorded.lock().unwrap(), &[7, 42]);
+    }
+
+    #[crate::runtime::test]
+    async fn extension_jump() {
+        let program = Memory::from(vec![1001, 8, 1, 8, 1060, 9, 0, 99, 0, 3]);
+        let mut vm = Vm::new(program.clone());
+        vm.extension(60, Loop);
+        vm.run().await.unwrap();
+        assert_eq!(vm.memory().get(8), 3);
+        assert_eq!(vm.memory().get(9), 0);
+
+        let program = Memory::from(vec![1060, 3, 0, 0, 99]);
+        let mut vm = Vm::new(program);
+        vm.extension(60, Loop);
+        assert!(matches!(
+            vm.run().await,
+            Err(Error::Extension { ip: 0, ref message }) if message == "counter exhausted"
+        ));
+    }
+
+    #[crate::runtime::test]
+    async fn unknown_opcode() {
+        let program = Memory::from(vec![1101, 1, 2, 0, 42, 99]);
+        let mut vm = Vm::new(program);
+        assert!(matches!(
+            vm.run().await,
+            Err(Error::UnknownOpcode { ip: 4, opcode: 42 })
+        ));
+    }
+
+    #[crate::runtime::test]
+    async fn invalid_parameter_mode() {
+        let program = Memory::from(vec![301, 1, 2, 0, 99]);
+        let mut vm = Vm::new(program);
+        assert!(matches!(
+            vm.run().await,
+            Err(Error::InvalidInstruction { ip: 0, ref instruction }) if instruction == "301"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Opcode 1 is not available for extensions")]
+    fn extension_builtin_opcode() {
+        Vm::new(Memory::from(vec![99])).extension(1, Loop);
+    }
+
+    #[crate::runtime::test]
+    async fn relative_mode() {
+        let program = Memory::from(vec![109, 7, 204, 1, 21101, 1, 2, 2, 99, 42]);
+        let mut vm = Vm::new(program);
+        assert_eq!(vm.run_and_collect().await.unwrap(), &[99]);
+        assert_eq!(vm.relative_base(), 7);
+        assert_eq!(vm.memory().get(9), 3);
     }
 }
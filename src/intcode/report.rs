@@ -0,0 +1,21 @@
+//! Advent of Code 2019: Intcode run reports
+
+use intcode_core::Address;
+use std::time::Duration;
+
+/// Execution statistics of running a program (see `Vm::run`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// Number of instructions executed
+    pub steps: usize,
+    /// Number of input values consumed
+    pub inputs: usize,
+    /// Number of output values produced
+    pub outputs: usize,
+    /// Instruction pointer after running
+    pub ip: Address,
+    /// Highest memory address touched by an instruction or its parameters (if any)
+    pub max_address: Option<Address>,
+    /// Wall clock time spent running
+    pub elapsed: Duration,
+}
@@ -0,0 +1,214 @@
+//! Advent of Code 2019: Intcode multi-VM scheduler
+
+use super::state::State;
+use super::vm::Vm;
+use crate::runtime;
+use async_channel::{self as channel, Sender};
+use futures_util::stream::{Stream, StreamExt};
+use futures_util::FutureExt;
+use intcode_core::{Error, Instruction, Value, Word};
+use std::{error, fmt};
+
+/// Destination of output values of a scheduled vm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// Output values are collected as output of the scheduler
+    Output,
+    /// Output values are sent to the vm with the given index
+    Vm(usize),
+}
+
+/// Virtual machine run by a scheduler
+struct Scheduled<V: Word> {
+    /// Virtual machine
+    vm: Vm<V>,
+    /// Sender for input values of the vm
+    input: Sender<V>,
+    /// Output stream of the vm
+    output: Box<dyn Stream<Item = V> + Unpin + Send>,
+    /// Destination of output values
+    target: Target,
+}
+
+impl<V: Word> fmt::Debug for Scheduled<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduled")
+            .field("vm", &self.vm)
+            .field("pending-inputs", &self.input.len())
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+impl<V: Word> Scheduled<V> {
+    /// Return true if the vm needs an input value but none is pending
+    fn is_waiting_for_input(&self) -> bool {
+        self.input.is_empty()
+            && !self.vm.has_pending_input()
+            && matches!(
+                Instruction::decode(self.vm.memory(), self.vm.ip()),
+                Ok(Instruction::Input(_))
+            )
+    }
+}
+
+/// Error returned when running scheduled virtual machines fails
+#[derive(Debug)]
+pub enum ScheduleError<V: Word = Value> {
+    /// The vm with the given index failed
+    Failed {
+        /// Index of the failed vm
+        index: usize,
+        /// Error of the vm
+        error: Error,
+    },
+    /// All vms that aren't done wait for input, but no input values are pending
+    Deadlock {
+        /// States of all vms (by index)
+        states: Vec<State<V>>,
+    },
+}
+
+impl<V: Word> fmt::Display for ScheduleError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::Failed { index, error } => write!(f, "Vm {} failed: {}", index, error),
+            ScheduleError::Deadlock { states } => {
+                write!(f, "Deadlock:")?;
+                for (index, state) in states.iter().enumerate() {
+                    let status = if state.done {
+                        "halted"
+                    } else {
+                        "waits for input"
+                    };
+                    let sep = if index == 0 { "" } else { "," };
+                    write!(f, "{} vm {} {} at address {}", sep, index, status, state.ip)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<V: Word> error::Error for ScheduleError<V> {}
+
+/// Scheduler that runs connected virtual machines deterministically on the current task
+///
+/// Vms take turns in the order they were added, each running until it needs an input value that
+/// isn't available yet, halts or executed a number of steps. Output values are passed on
+/// between turns, so every run of the same setup interleaves the vms in the same way. If all vms
+/// that aren't done wait for input and no input values are pending, the vms can never continue
+/// and running fails with a deadlock instead of waiting forever.
+#[derive(Debug)]
+pub struct Scheduler<V: Word = Value> {
+    /// Scheduled vms
+    vms: Vec<Scheduled<V>>,
+}
+
+impl<V: Word> Default for Scheduler<V> {
+    fn default() -> Self {
+        Self { vms: Vec::new() }
+    }
+}
+
+impl<V: Word> Scheduler<V> {
+    /// Maximum number of steps a vm executes before the next vm takes its turn
+    const SLICE_STEPS: usize = 1000;
+
+    /// Create new scheduler without vms
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the given vm and return its index
+    ///
+    /// Input and output of the vm are connected to the scheduler, so the vm must not have an
+    /// output stream set. Output values are collected as output of the scheduler unless the vm is
+    /// connected to another vm.
+    pub fn add(&mut self, mut vm: Vm<V>) -> usize {
+        let (tx, rx) = channel::unbounded();
+        vm.input(rx);
+        let output = Box::new(vm.output());
+        self.vms.push(Scheduled {
+            vm,
+            input: tx,
+            output,
+            target: Target::Output,
+        });
+        self.vms.len() - 1
+    }
+
+    /// Send output values of the vm `from` to the vm `to` as input values
+    pub fn connect(&mut self, from: usize, to: usize) -> &mut Self {
+        assert!(to < self.vms.len(), "No vm with index {}", to);
+        self.vms[from].target = Target::Vm(to);
+        self
+    }
+
+    /// Queue the given input value for the vm with the given index
+    pub fn send(&mut self, index: usize, value: V) -> &mut Self {
+        // Sending to an unbounded channel only fails if it's closed, i.e. if the vm is done
+        let _ = self.vms[index].input.try_send(value);
+        self
+    }
+
+    /// Return the vm with the given index
+    pub fn vm(&self, index: usize) -> &Vm<V> {
+        &self.vms[index].vm
+    }
+
+    /// Remove all vms and return them in the order they were added
+    pub fn into_vms(self) -> Vec<Vm<V>> {
+        self.vms.into_iter().map(|scheduled| scheduled.vm).collect()
+    }
+
+    /// Run one turn of the vm with the given index and return the number of executed steps
+    fn run_turn(&mut self, index: usize, output: &mut Vec<V>) -> Result<usize, ScheduleError<V>> {
+        let scheduled = &mut self.vms[index];
+        let mut values = Vec::new();
+        let mut steps = 0;
+        while steps < Self::SLICE_STEPS
+            && !scheduled.vm.is_done()
+            && !scheduled.is_waiting_for_input()
+        {
+            // Input is available and output is drained after every step, so the step never waits
+            match scheduled.vm.step().now_or_never() {
+                Some(Ok(())) => steps += 1,
+                Some(Err(error)) => return Err(ScheduleError::Failed { index, error }),
+                None => break,
+            }
+            while let Some(Some(value)) = scheduled.output.next().now_or_never() {
+                values.push(value);
+            }
+        }
+        match scheduled.target {
+            Target::Output => output.extend(values),
+            Target::Vm(to) => {
+                for value in values {
+                    self.send(to, value);
+                }
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Run all vms until all are done and return the collected output values (in the order
+    /// they were produced)
+    ///
+    /// Fails if a vm fails or if the vms deadlock (the error lists the states of all vms).
+    pub async fn run(&mut self) -> Result<Vec<V>, ScheduleError<V>> {
+        let mut output = Vec::new();
+        while !self.vms.iter().all(|scheduled| scheduled.vm.is_done()) {
+            let mut steps = 0;
+            for index in 0..self.vms.len() {
+                steps += self.run_turn(index, &mut output)?;
+            }
+            if steps == 0 {
+                let states = self.vms.iter().map(|s| s.vm.state()).collect();
+                return Err(ScheduleError::Deadlock { states });
+            }
+            runtime::yield_now().await;
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,41 @@
+//! Advent of Code 2019: Intcode VM input handle
+
+use async_channel::Sender;
+use intcode_core::{Value, Word};
+
+/// Handle for pushing input values to a virtual machine (see `Vm::input_sender`)
+///
+/// Values can be sent at any time, also while the vm is running (e.g. from another task or
+/// thread). Once the input is closed (or all handles are dropped) and all values sent were
+/// consumed, running the vm fails with `Error::Input` when it needs another input value.
+#[derive(Debug, Clone)]
+pub struct InputSender<V: Word = Value> {
+    tx: Sender<V>,
+}
+
+impl<V: Word> InputSender<V> {
+    /// Create new handle for the given channel
+    pub(super) fn new(tx: Sender<V>) -> Self {
+        Self { tx }
+    }
+
+    /// Send input value to the vm
+    ///
+    /// Fails (returning the value) if the input was closed or the vm doesn't use it anymore
+    /// (because it halted or another input was set).
+    pub fn send(&self, value: V) -> Result<(), V> {
+        self.tx.try_send(value).map_err(|err| err.into_inner())
+    }
+
+    /// Close the input, so that no more values can be sent
+    ///
+    /// Returns false if the input was closed already.
+    pub fn close(&self) -> bool {
+        self.tx.close()
+    }
+
+    /// Return true if the input was closed (or the vm doesn't use it anymore)
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
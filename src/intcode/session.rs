@@ -0,0 +1,200 @@
+//! Advent of Code 2019: Intcode I/O sessions
+
+use intcode_core::{Value, Word};
+use std::str::FromStr;
+use std::{error, fmt};
+
+/// Input or output value exchanged by a virtual machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event<V = Value> {
+    /// Value was read by an input instruction
+    Input {
+        /// Number of steps executed before the instruction
+        step: usize,
+        /// Value read
+        value: V,
+    },
+    /// Value was written by an output instruction
+    Output {
+        /// Number of steps executed before the instruction
+        step: usize,
+        /// Value written
+        value: V,
+    },
+}
+
+impl<V> Event<V> {
+    /// Return the number of steps executed before the event
+    pub fn step(&self) -> usize {
+        match self {
+            Event::Input { step, .. } | Event::Output { step, .. } => *step,
+        }
+    }
+
+    /// Return the value exchanged
+    pub fn value(&self) -> &V {
+        match self {
+            Event::Input { value, .. } | Event::Output { value, .. } => value,
+        }
+    }
+}
+
+impl<V: fmt::Display> fmt::Display for Event<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Input { step, value } => write!(f, "in {} {}", step, value),
+            Event::Output { step, value } => write!(f, "out {} {}", step, value),
+        }
+    }
+}
+
+/// Recorded I/O session of a virtual machine (see `Vm::record` and `Vm::replay`)
+///
+/// A session is saved as text with one event per line, e.g. `in 12 5` for an input value 5
+/// read after 12 steps, or `out 30 -7` for an output value -7 written after 30 steps. Empty lines
+/// and lines starting with `#` are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session<V = Value> {
+    /// Recorded events in order
+    events: Vec<Event<V>>,
+}
+
+impl<V> Default for Session<V> {
+    fn default() -> Self {
+        Self { events: Vec::new() }
+    }
+}
+
+impl<V: Word> Session<V> {
+    /// Create new empty session
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append event
+    pub fn push(&mut self, event: Event<V>) {
+        self.events.push(event);
+    }
+
+    /// Remove and return the last event
+    pub fn pop(&mut self) -> Option<Event<V>> {
+        self.events.pop()
+    }
+
+    /// Return all events in order
+    pub fn events(&self) -> &[Event<V>] {
+        &self.events
+    }
+
+    /// Return all input values in order
+    pub fn inputs(&self) -> impl Iterator<Item = &V> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            Event::Input { value, .. } => Some(value),
+            Event::Output { .. } => None,
+        })
+    }
+
+    /// Return all output values in order
+    pub fn outputs(&self) -> impl Iterator<Item = &V> + '_ {
+        self.events.iter().filter_map(|event| match event {
+            Event::Output { value, .. } => Some(value),
+            Event::Input { .. } => None,
+        })
+    }
+}
+
+impl<V> From<Session<V>> for Vec<Event<V>> {
+    fn from(session: Session<V>) -> Self {
+        session.events
+    }
+}
+
+impl<V: fmt::Display> fmt::Display for Session<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when parsing a session fails
+#[derive(Debug)]
+pub struct ParseSessionError {
+    /// Line number (starting at 1)
+    pub line: usize,
+    /// Text of the line
+    pub text: String,
+}
+
+impl fmt::Display for ParseSessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid session event in line {}: {}",
+            self.line, self.text
+        )
+    }
+}
+
+impl error::Error for ParseSessionError {}
+
+impl<V: Word + FromStr> FromStr for Session<V> {
+    type Err = ParseSessionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut session = Self::new();
+        for (idx, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || ParseSessionError {
+                line: idx + 1,
+                text: line.to_string(),
+            };
+            let mut parts = line.split_whitespace();
+            let (kind, step, value) = match (parts.next(), parts.next(), parts.next(), parts.next())
+            {
+                (Some(kind), Some(step), Some(value), None) => (kind, step, value),
+                _ => return Err(error()),
+            };
+            let step = step.parse().map_err(|_| error())?;
+            let value = value.parse().map_err(|_| error())?;
+            session.push(match kind {
+                "in" => Event::Input { step, value },
+                "out" => Event::Output { step, value },
+                _ => return Err(error()),
+            });
+        }
+        Ok(session)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_format() {
+        let mut session = Session::new();
+        session.push(Event::Input { step: 0, value: 5 });
+        session.push(Event::Output { step: 3, value: -7 });
+        let text = session.to_string();
+        assert_eq!(text, "in 0 5\nout 3 -7\n");
+        assert_eq!(text.parse::<Session>().unwrap(), session);
+        assert_eq!(
+            "# comment\n\n in 0 5 \nout 3 -7"
+                .parse::<Session>()
+                .unwrap(),
+            session
+        );
+        assert_eq!(session.inputs().collect::<Vec<_>>(), [&5]);
+        assert_eq!(session.outputs().collect::<Vec<_>>(), [&-7]);
+
+        let err = "in 0 5\nout x 1".parse::<Session>().unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!("in 0 5 6".parse::<Session>().is_err());
+        assert!("put 0 5".parse::<Session>().is_err());
+    }
+}
@@ -0,0 +1,22 @@
+//! Advent of Code 2019: Intcode VM state
+
+use intcode_core::{Address, Memory, Value, Word};
+
+/// Snapshot of the memory and registers of a virtual machine (see `Vm::state`)
+///
+/// With the `serde` feature enabled, states can be serialized to store them or send them to
+/// other processes, and restored into a virtual machine later (see `Vm::restore`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct State<V: Word = Value> {
+    /// Memory
+    pub memory: Memory<V>,
+    /// Instruction pointer (address of next instruction)
+    pub ip: Address,
+    /// Relative base (base address for relative mode parameters)
+    pub relative_base: Address,
+    /// Number of steps executed
+    pub steps: usize,
+    /// Flag to signal that the program is done
+    pub done: bool,
+}
@@ -0,0 +1,88 @@
+//! Advent of Code 2019: Intcode undo log
+
+use super::flow::FlowUndo;
+use super::session::Event;
+use intcode_core::{Address, Memory, Word};
+use std::collections::VecDeque;
+
+/// State needed to undo one step of a virtual machine
+#[derive(Debug)]
+pub(super) struct Entry<V: Word> {
+    /// Instruction pointer before the step
+    pub ip: Address,
+    /// Relative base before the step
+    pub relative_base: Address,
+    /// Done flag before the step
+    pub done: bool,
+    /// Address and previous value of the memory word written by the step
+    pub write: Option<(Address, V)>,
+    /// Memory before the step (for steps whose writes are unknown, like extension instructions)
+    pub snapshot: Option<Memory<V>>,
+    /// Input value read by the step
+    pub input: Option<V>,
+    /// Flag to signal that the step wrote an output value
+    pub output: bool,
+    /// Event of a replayed session consumed by the step
+    pub replayed: Option<Event<V>>,
+    /// Flag to signal that the step added an event to the recorded session
+    pub recorded: bool,
+    /// Data flow change of the step (if tracked)
+    pub flow: Option<FlowUndo>,
+}
+
+impl<V: Word> Entry<V> {
+    /// Return true if the step wrote to the given address (given the memory after the step)
+    pub fn writes(&self, addr: Address, memory: &Memory<V>) -> bool {
+        match (&self.write, &self.snapshot) {
+            (Some((write, _)), _) => *write == addr,
+            (None, Some(snapshot)) => {
+                addr < memory.size() && snapshot.get(addr) != memory.get(addr)
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+/// Log of the most recent steps of a virtual machine (see `Vm::undo_log`)
+#[derive(Debug)]
+pub(super) struct UndoLog<V: Word> {
+    /// Maximum number of entries kept
+    capacity: usize,
+    /// Entries, most recent step last
+    entries: VecDeque<Entry<V>>,
+}
+
+impl<V: Word> UndoLog<V> {
+    /// Create new empty log keeping at most the given number of entries
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Add entry, dropping the oldest entry if the log is full
+    pub fn push(&mut self, entry: Entry<V>) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        if self.capacity > 0 {
+            self.entries.push_back(entry);
+        }
+    }
+
+    /// Remove and return the most recent entry
+    pub fn pop(&mut self) -> Option<Entry<V>> {
+        self.entries.pop_back()
+    }
+
+    /// Return the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
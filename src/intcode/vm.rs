@@ -1,302 +1,735 @@
 //! Advent of Code 2019: Intcode VM
 
-use super::memory::{Address, Memory, Value};
-use async_std::channel::{self, Sender};
-use async_std::prelude::*;
-use std::fmt;
+use super::cancel::CancellationToken;
+use super::flow::DataFlow;
+use super::report::Report;
+use super::sender::InputSender;
+use super::session::{Event, Session};
+use super::state::State;
+use super::undo::{Entry, UndoLog};
+use crate::runtime::{self, JoinHandle};
+use async_channel::{self as channel, Sender};
+use futures_util::future;
+use futures_util::stream::{Stream, StreamExt};
+use intcode_core::{
+    Address, Arithmetic, Effect, Error, Extension, Instruction, Machine, Memory, Value, Word,
+};
+use std::collections::VecDeque;
+use std::time::Instant;
+use std::{fmt, iter};
+use tracing::{debug, debug_span, trace, Instrument};
 
-/// Intcode parameter
+/// Error of a fallible input stream (see `Vm::try_input`)
+pub type InputError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Intcode virtual machine
 ///
-/// Instructions in Intcode use a certain number of parameters in certain parameter modes. The
-/// mode of a parameter determines how the parameter is used to fetch or store the actual value.
-#[derive(Debug)]
-enum Param {
-    /// Position mode: parameter points to an address containing the value
-    Position(Address),
-    /// Immediate mode: parameter is used as the value
-    Immediate(Value),
+/// The virtual machine operates on words of type `V` (`i32` by default). Arithmetic instructions
+/// follow the arithmetic policy of the machine, which defaults to failing with an error on
+/// overflow. Virtual machines are `Send`, so they can be run on separate tasks on a multi-threaded
+/// executor (see `Vm::spawn` and `Vm::run_all`).
+pub struct Vm<V: Word = Value> {
+    /// Original program image (restored on reset)
+    program: Memory<V>,
+    /// Machine executing the instructions (memory, registers and instruction set)
+    machine: Machine<V>,
+    /// Input channel for receiving input values (or descriptions of input failures)
+    input: Option<Box<dyn Stream<Item = Result<V, InputError>> + Unpin + Send>>,
+    /// Output channel for sending output values
+    output: Option<Sender<V>>,
+    /// Number of steps executed
+    steps: usize,
+    /// Number of input values consumed
+    inputs: usize,
+    /// Number of output values produced
+    outputs: usize,
+    /// Highest memory address touched since the last run started
+    max_address: Option<Address>,
+    /// Session that input and output values are recorded into
+    recording: Option<Session<V>>,
+    /// Remaining events of a session being replayed
+    replay: Option<VecDeque<Event<V>>>,
+    /// Log of recent steps for stepping backwards
+    undo: Option<UndoLog<V>>,
+    /// Input values taken back by stepping backwards (read again before any other input)
+    rewound_inputs: VecDeque<V>,
+    /// Data flow of input values (if tracked)
+    flow: Option<DataFlow<V>>,
 }
 
-impl fmt::Display for Param {
+impl<V: Word> fmt::Debug for Vm<V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Param::Position(addr) => write!(f, "[{}]", addr),
-            Param::Immediate(value) => write!(f, "{}", value),
-        }
+        f.debug_struct("Vm")
+            .field("memory", &self.machine.memory)
+            .field("ip", &self.machine.ip)
+            .field("relative-base", &self.machine.relative_base)
+            .field("arithmetic", &self.machine.arithmetic)
+            .field(
+                "extensions",
+                &self.machine.extension_opcodes().collect::<Vec<_>>(),
+            )
+            .field("input-present", &self.input.is_some())
+            .field("output-present", &self.output.is_some())
+            .field("done", &self.machine.done)
+            .field("steps", &self.steps)
+            .field("recording", &self.recording.is_some())
+            .field("replaying", &self.replay.is_some())
+            .field("undo-steps", &self.undo.as_ref().map(UndoLog::len))
+            .field("data-flow", &self.flow.is_some())
+            .finish()
     }
 }
 
-impl Param {
-    /// Parse parameter with the given number from memory slice of the instruction
-    fn parse(mem: &[Value], n: usize) -> Self {
-        debug_assert!(n < 3, "Parameter {} out of range", n);
-        let div = (10_i32).pow(n as u32) * 100;
-        match mem[0] / div % 10 {
-            0 => Param::Position(mem[1 + n] as Address),
-            1 => Param::Immediate(mem[1 + n]),
-            mode => panic!(
-                "Unknown parameter mode {} for parameter {} in instruction {}",
-                mode, n, mem[0],
-            ),
+impl<V: Word> From<Memory<V>> for Vm<V> {
+    fn from(memory: Memory<V>) -> Self {
+        Self {
+            program: memory.clone(),
+            machine: Machine::new(memory),
+            input: None,
+            output: None,
+            steps: 0,
+            inputs: 0,
+            outputs: 0,
+            max_address: None,
+            recording: None,
+            replay: None,
+            undo: None,
+            rewound_inputs: VecDeque::new(),
+            flow: None,
         }
     }
+}
 
-    /// Fetch value for this parameter
-    fn fetch(&self, memory: &Memory) -> Value {
-        match self {
-            Param::Position(address) => memory.get(*address),
-            Param::Immediate(value) => *value,
-        }
+/// Create a virtual machine that continues from the given state (the state's memory is also used
+/// as the program image to restore on reset)
+impl<V: Word> From<State<V>> for Vm<V> {
+    fn from(state: State<V>) -> Self {
+        let mut vm = Self::from(state.memory.clone());
+        vm.restore(state);
+        vm
+    }
+}
+
+impl<V: Word> Vm<V> {
+    /// Number of steps after which a running vm yields to the executor
+    const YIELD_INTERVAL: usize = 1000;
+
+    /// Create new virtual machine with the given program memory
+    pub fn new(program: Memory<V>) -> Self {
+        Self::from(program)
     }
 
-    /// Store value into this parameter
-    fn store(&self, memory: &mut Memory, value: Value) {
-        match self {
-            Param::Position(address) => memory.set(*address, value),
-            Param::Immediate(_value) => panic!("Can't store to immediate mode parameter"),
+    /// Reset the vm to its initial state
+    ///
+    /// Restores the original program image and registers and detaches input and output (as well
+    /// as a recorded or replayed session), so that the program can be run again with new input
+    /// and output. Since memory pages are shared until written, this is much cheaper than loading
+    /// the program again.
+    pub fn reset(&mut self) -> &mut Self {
+        self.machine.memory = self.program.clone();
+        self.machine.ip = Address::default();
+        self.machine.relative_base = Address::default();
+        self.input = None;
+        self.output = None;
+        self.machine.done = false;
+        self.steps = 0;
+        self.inputs = 0;
+        self.outputs = 0;
+        self.max_address = None;
+        self.recording = None;
+        self.replay = None;
+        if let Some(undo) = self.undo.as_mut() {
+            undo.clear();
+        }
+        self.rewound_inputs.clear();
+        if self.flow.is_some() {
+            self.flow = Some(DataFlow::default());
         }
+        self
     }
-}
 
-/// Intcode instruction
-///
-/// Instructions in Intcode consist of the opcode that determines the operation and zero or more
-/// parameters depending on which opcode is used.
-#[derive(Debug)]
-enum Instruction {
-    /// Addition. Adds p1 and p2 and stores the sum in p3
-    Add(Param, Param, Param),
-    /// Addition. Multiplies p1 and p2 and stores the product in p3
-    Multiply(Param, Param, Param),
-    /// Get value from input and store it in p1
-    Input(Param),
-    /// Output value
-    Output(Param),
-    /// Jump if true / not zero: set instruction pointer to p2 if p1 is not zero
-    JumpIfNotZero(Param, Param),
-    /// Jump if false / zero: set instruction pointer to p2 if p1 is zero
-    JumpIfZero(Param, Param),
-    /// Less than: if p1 is less than p2, stores 1 to p3, 0 otherwise
-    LessThan(Param, Param, Param),
-    /// Equals: if p1 equals p2, stores 1 to p3, 0 otherwise
-    Equals(Param, Param, Param),
-    /// Program done
-    Done,
-}
+    /// Set noun (value at memory address 1)
+    pub fn noun(&mut self, noun: V) -> &mut Self {
+        assert!(noun <= V::from(99));
+        self.machine.memory.set(1, noun);
+        self
+    }
 
-impl fmt::Display for Instruction {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Instruction::Add(p1, p2, p3) => write!(f, "add {} {} {}", p1, p2, p3),
-            Instruction::Multiply(p1, p2, p3) => write!(f, "mul {} {} {}", p1, p2, p3),
-            Instruction::Input(p1) => write!(f, "in {}", p1),
-            Instruction::Output(p1) => write!(f, "out {}", p1),
-            Instruction::JumpIfNotZero(p1, p2) => write!(f, "jnz {} {}", p1, p2),
-            Instruction::JumpIfZero(p1, p2) => write!(f, "jz  {} {}", p1, p2),
-            Instruction::LessThan(p1, p2, p3) => write!(f, "lt  {} {} {}", p1, p2, p3),
-            Instruction::Equals(p1, p2, p3) => write!(f, "eq  {} {} {}", p1, p2, p3),
-            Instruction::Done => write!(f, "done"),
+    /// Set verb (value at memory address 2)
+    pub fn verb(&mut self, verb: V) -> &mut Self {
+        assert!(verb <= V::from(99));
+        self.machine.memory.set(2, verb);
+        self
+    }
+
+    /// Set arithmetic policy for handling overflows
+    pub fn arithmetic(&mut self, arithmetic: Arithmetic) -> &mut Self {
+        self.machine.arithmetic = arithmetic;
+        self
+    }
+
+    /// Register an instruction set extension for the given opcode
+    ///
+    /// Panics if the opcode is not a two digit number or is used by a builtin instruction.
+    pub fn extension(&mut self, opcode: u8, extension: impl Extension<V> + 'static) -> &mut Self {
+        self.machine.extension(opcode, extension);
+        self
+    }
+
+    /// Set stream that yields input values for the vm
+    ///
+    /// If the stream ends while the program needs another input value, running the vm fails with
+    /// `Error::Input`.
+    pub fn input(&mut self, input: impl Stream<Item = V> + Unpin + Send + 'static) -> &mut Self {
+        self.input = Some(Box::new(input.map(Ok)));
+        self
+    }
+
+    /// Set fallible stream that yields input values for the vm
+    ///
+    /// If the stream yields an error, running the vm fails with `Error::Input` at the input
+    /// instruction that needed the value, with the error of the stream as source. The instruction
+    /// isn't executed, so the vm can be resumed after setting another input stream and output
+    /// (both are closed when running fails, see `run`).
+    pub fn try_input<E: Into<InputError>>(
+        &mut self,
+        input: impl Stream<Item = Result<V, E>> + Unpin + Send + 'static,
+    ) -> &mut Self {
+        self.input = Some(Box::new(input.map(|value| value.map_err(Into::into))));
+        self
+    }
+
+    /// Return a handle for pushing input values to the vm
+    ///
+    /// The input of the vm is replaced by a channel that values can be sent to at any time. Once
+    /// the input is closed and all sent values were consumed, running the vm fails with
+    /// `Error::Input` when it needs another input value.
+    pub fn input_sender(&mut self) -> InputSender<V> {
+        let (tx, rx) = channel::unbounded();
+        self.input = Some(Box::new(rx.map(Ok)));
+        InputSender::new(tx)
+    }
+
+    /// Start recording all input and output values into a new session
+    pub fn record(&mut self) -> &mut Self {
+        self.recording = Some(Session::new());
+        self
+    }
+
+    /// Return the session recorded so far
+    pub fn session(&self) -> Option<&Session<V>> {
+        self.recording.as_ref()
+    }
+
+    /// Stop recording and return the recorded session
+    pub fn take_session(&mut self) -> Option<Session<V>> {
+        self.recording.take()
+    }
+
+    /// Replay a recorded session
+    ///
+    /// Input values are taken from the session instead of the input stream, and every output
+    /// value is verified against the session (output values are still sent to the output stream
+    /// if one is set). Running the program fails with `Error::ReplayMismatch` as soon as an input
+    /// or output happens at another step than recorded, an output value differs or the program
+    /// halts before all events are replayed.
+    pub fn replay(&mut self, session: Session<V>) -> &mut Self {
+        self.replay = Some(Vec::from(session).into());
+        self
+    }
+
+    /// Verify the given event against the next event of a replayed session without consuming it.
+    /// Returns whether a session is replayed.
+    fn check_replay(&self, found: &Event<V>) -> Result<bool, Error> {
+        let replay = match self.replay.as_ref() {
+            Some(replay) => replay,
+            None => return Ok(false),
+        };
+        let matches = match (replay.front(), found) {
+            (Some(Event::Input { step, .. }), Event::Input { step: found, .. }) => step == found,
+            (Some(expected @ Event::Output { .. }), Event::Output { .. }) => expected == found,
+            _ => false,
+        };
+        if matches {
+            Ok(true)
+        } else {
+            let expected = replay
+                .front()
+                .map_or("end of session".to_string(), |e| e.to_string());
+            let found = match found {
+                Event::Input { step, .. } => format!("input at step {}", step),
+                event => event.to_string(),
+            };
+            Err(Error::ReplayMismatch {
+                ip: self.machine.ip,
+                expected,
+                found,
+            })
         }
     }
-}
 
-impl Instruction {
-    /// Parse instruction from memory slice
-    fn parse(mem: &[Value]) -> Self {
-        match mem[0] % 100 {
-            1 => Instruction::Add(
-                Param::parse(mem, 0),
-                Param::parse(mem, 1),
-                Param::parse(mem, 2),
-            ),
-            2 => Instruction::Multiply(
-                Param::parse(mem, 0),
-                Param::parse(mem, 1),
-                Param::parse(mem, 2),
-            ),
-            3 => Instruction::Input(Param::parse(mem, 0)),
-            4 => Instruction::Output(Param::parse(mem, 0)),
-            5 => Instruction::JumpIfNotZero(Param::parse(mem, 0), Param::parse(mem, 1)),
-            6 => Instruction::JumpIfZero(Param::parse(mem, 0), Param::parse(mem, 1)),
-            7 => Instruction::LessThan(
-                Param::parse(mem, 0),
-                Param::parse(mem, 1),
-                Param::parse(mem, 2),
-            ),
-            8 => Instruction::Equals(
-                Param::parse(mem, 0),
-                Param::parse(mem, 1),
-                Param::parse(mem, 2),
-            ),
-            99 => Instruction::Done,
-            opcode => panic!("Unknown opcode {}", opcode),
+    /// Verify the given event against the next event of a replayed session. Returns the recorded
+    /// event if it matches.
+    fn replay_event(&mut self, found: Event<V>) -> Result<Option<Event<V>>, Error> {
+        if self.check_replay(&found)? {
+            Ok(self.replay.as_mut().and_then(|replay| replay.pop_front()))
+        } else {
+            Ok(None)
         }
     }
 
-    /// Execute instruction
-    async fn execute(&self, vm: &mut Vm) {
-        match self {
-            Instruction::Add(p1, p2, p3) => {
-                let result = p1.fetch(&vm.memory) + p2.fetch(&vm.memory);
-                p3.store(&mut vm.memory, result);
-                vm.ip += 4;
-            }
-            Instruction::Multiply(p1, p2, p3) => {
-                let result = p1.fetch(&vm.memory) * p2.fetch(&vm.memory);
-                p3.store(&mut vm.memory, result);
-                vm.ip += 4;
-            }
-            Instruction::Input(p1) => {
-                let rx = vm.input.as_mut().expect("No input channel set");
-                let value = rx
-                    .next()
-                    .await
-                    .expect("No input values left (input channel closed)");
-                p1.store(&mut vm.memory, value);
-                vm.ip += 2;
-            }
-            Instruction::Output(p1) => {
-                let tx = vm.output.as_mut().expect("No output channel set");
-                tx.send(p1.fetch(&vm.memory)).await.unwrap();
-                vm.ip += 2;
-            }
-            Instruction::JumpIfNotZero(p1, p2) => {
-                if p1.fetch(&vm.memory) != 0 {
-                    vm.ip = p2.fetch(&vm.memory) as Address;
-                } else {
-                    vm.ip += 3;
-                }
+    /// Execute the given instruction
+    ///
+    /// Instruction semantics are implemented by the machine, while input and output (including
+    /// recording and replaying sessions) are handled here. Returns the event taken from a replayed
+    /// session (if any).
+    async fn execute(&mut self, instruction: &Instruction<V>) -> Result<Option<Event<V>>, Error> {
+        if let Instruction::Done = instruction {
+            if let Some(event) = self.replay.as_ref().and_then(|replay| replay.front()) {
+                return Err(Error::ReplayMismatch {
+                    ip: self.machine.ip,
+                    expected: event.to_string(),
+                    found: format!("halt at step {}", self.steps),
+                });
             }
-            Instruction::JumpIfZero(p1, p2) => {
-                if p1.fetch(&vm.memory) == 0 {
-                    vm.ip = p2.fetch(&vm.memory) as Address;
-                } else {
-                    vm.ip += 3;
+        }
+        let mut replayed = None;
+        match self.machine.execute(instruction)? {
+            Effect::None => (),
+            Effect::Input => {
+                let event = Event::Input {
+                    step: self.steps,
+                    value: V::from(0),
+                };
+                let value = match self.rewound_inputs.pop_front() {
+                    Some(value) => value,
+                    None => match self.replay_event(event)? {
+                        Some(event) => {
+                            let value = event.value().clone();
+                            replayed = Some(event);
+                            value
+                        }
+                        None => {
+                            let value = match self.input.as_mut() {
+                                Some(rx) => rx
+                                    .next()
+                                    .await
+                                    .unwrap_or_else(|| Err("No input values left".into())),
+                                None => Err("No input stream set".into()),
+                            };
+                            value.map_err(|source| {
+                                debug!(ip = self.machine.ip, error = %source, "Input failed");
+                                Error::Input {
+                                    ip: self.machine.ip,
+                                    source,
+                                }
+                            })?
+                        }
+                    },
+                };
+                debug!(ip = self.machine.ip, step = self.steps, %value, "Input value");
+                if let Some(session) = self.recording.as_mut() {
+                    session.push(Event::Input {
+                        step: self.steps,
+                        value: value.clone(),
+                    });
                 }
+                self.machine.finish_input(instruction, value);
             }
-            Instruction::LessThan(p1, p2, p3) => {
-                if p1.fetch(&vm.memory) < p2.fetch(&vm.memory) {
-                    p3.store(&mut vm.memory, 1);
-                } else {
-                    p3.store(&mut vm.memory, 0);
+            Effect::Output(value) => {
+                let event = Event::Output {
+                    step: self.steps,
+                    value: value.clone(),
+                };
+                debug!(ip = self.machine.ip, step = self.steps, %value, "Output value");
+                let replaying = self.check_replay(&event)?;
+                let sent = match self.output.as_mut() {
+                    Some(tx) => tx.send(value).await.is_ok(),
+                    None => replaying,
+                };
+                if !sent {
+                    debug!(ip = self.machine.ip, "Output closed");
+                    return Err(Error::Output {
+                        ip: self.machine.ip,
+                    });
                 }
-                vm.ip += 4;
-            }
-            Instruction::Equals(p1, p2, p3) => {
-                if p1.fetch(&vm.memory) == p2.fetch(&vm.memory) {
-                    p3.store(&mut vm.memory, 1);
-                } else {
-                    p3.store(&mut vm.memory, 0);
+                // Only consume and record the event once the value is sent, since the send may
+                // be cancelled (and the instruction executed again later)
+                replayed = self.replay_event(event.clone())?;
+                if let Some(session) = self.recording.as_mut() {
+                    session.push(event);
                 }
-                vm.ip += 4;
+                self.machine.finish_output(instruction);
             }
-            Instruction::Done => {
-                vm.input = None;
-                vm.output = None;
-                vm.done = true;
+            Effect::Halt => {
+                debug!(ip = self.machine.ip, steps = self.steps, "Program halted");
+                self.replay = None;
+                self.input = None;
+                self.output = None;
             }
         }
+        Ok(replayed)
     }
-}
-
-/// Intcode virtual machine
-pub struct Vm {
-    /// Memory of the virtual machine
-    memory: Memory,
-    /// Instruction pointer (address of next instruction)
-    ip: Address,
-    /// Input channel for receiving input values
-    input: Option<Box<dyn Stream<Item = Value> + Unpin>>,
-    /// Output channel for sending output values
-    output: Option<Sender<Value>>,
-    /// Flag to signal that the program is done
-    done: bool,
-}
 
-impl fmt::Debug for Vm {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Vm")
-            .field("memory", &self.memory)
-            .field("ip", &self.ip)
-            .field("input-present", &self.input.is_some())
-            .field("output-present", &self.output.is_some())
-            .field("done", &self.done)
-            .finish()
+    /// Run one program step
+    pub async fn step(&mut self) -> Result<(), Error> {
+        let instruction = self.machine.decode()?;
+        trace!(ip = self.machine.ip, step = self.steps, %instruction, "Executing instruction");
+        let entry = self.undo.as_ref().map(|_| self.undo_entry(&instruction));
+        let change = self.flow.as_ref().map(|flow| {
+            flow.change(
+                &instruction,
+                &self.machine.memory,
+                self.machine.ip,
+                self.machine.relative_base,
+                self.steps,
+            )
+        });
+        let touched = instruction
+            .params()
+            .filter_map(|param| param.address_with(self.machine.relative_base))
+            .chain(iter::once(self.machine.ip + instruction.len() - 1))
+            .max();
+        let replayed = self.execute(&instruction).await?;
+        // Data flow is only changed once the step succeeded, since it may fail or be cancelled
+        let flow = match (self.flow.as_mut(), change) {
+            (Some(flow), Some(change)) => Some(flow.apply(change)),
+            _ => None,
+        };
+        self.steps += 1;
+        self.max_address = self.max_address.max(touched);
+        match instruction {
+            Instruction::Input(_) => self.inputs += 1,
+            Instruction::Output(_) => self.outputs += 1,
+            _ => (),
+        }
+        if let (Some(undo), Some(mut entry)) = (self.undo.as_mut(), entry) {
+            if let Instruction::Input(p1) = &instruction {
+                entry.input = Some(p1.fetch(&self.machine.memory, entry.relative_base));
+            }
+            entry.recorded = self.recording.is_some() && (entry.input.is_some() || entry.output);
+            entry.replayed = replayed;
+            entry.flow = flow;
+            undo.push(entry);
+        }
+        Ok(())
     }
-}
 
-impl From<Memory> for Vm {
-    fn from(memory: Memory) -> Self {
-        Self {
-            memory,
-            ip: Address::default(),
+    /// Create undo log entry for executing the given instruction
+    fn undo_entry(&self, instruction: &Instruction<V>) -> Entry<V> {
+        let write = instruction
+            .target()
+            .and_then(|param| param.address_with(self.machine.relative_base))
+            .filter(|addr| *addr < self.machine.memory.size())
+            .map(|addr| (addr, self.machine.memory.get(addr)));
+        let snapshot = match instruction {
+            Instruction::Extension(_, _) => Some(self.machine.memory.clone()),
+            _ => None,
+        };
+        Entry {
+            ip: self.machine.ip,
+            relative_base: self.machine.relative_base,
+            done: self.machine.done,
+            write,
+            snapshot,
             input: None,
-            output: None,
-            done: false,
+            output: matches!(instruction, Instruction::Output(_)),
+            replayed: None,
+            recorded: false,
+            flow: None,
         }
     }
-}
 
-impl Vm {
-    /// Create new virtual machine with the given program memory
-    pub fn new(program: Memory) -> Self {
-        Self::from(program)
+    /// Restore the state before the step of the given undo log entry
+    fn undo_step(&mut self, entry: Entry<V>) {
+        self.machine.ip = entry.ip;
+        self.machine.relative_base = entry.relative_base;
+        self.machine.done = entry.done;
+        self.steps -= 1;
+        if let Some((addr, value)) = entry.write {
+            self.machine.memory.set(addr, value);
+        }
+        if let Some(snapshot) = entry.snapshot {
+            self.machine.memory = snapshot;
+        }
+        match (entry.replayed, self.replay.as_mut()) {
+            (Some(event), Some(replay)) => replay.push_front(event),
+            _ => {
+                if let Some(value) = entry.input {
+                    self.rewound_inputs.push_front(value);
+                }
+            }
+        }
+        if let (Some(flow), Some(undo)) = (self.flow.as_mut(), entry.flow) {
+            flow.revert(undo);
+        }
+        if entry.recorded {
+            if let Some(session) = self.recording.as_mut() {
+                session.pop();
+            }
+        }
     }
 
-    /// Set noun (value at memory address 1)
-    pub fn noun(&mut self, noun: Value) -> &mut Self {
-        assert!(noun <= 99);
-        self.memory.set(1, noun);
+    /// Enable tracking of the data flow of input values (see `DataFlow`)
+    pub fn track_data_flow(&mut self) -> &mut Self {
+        self.flow = Some(DataFlow::default());
         self
     }
 
-    /// Set verb (value at memory address 2)
-    pub fn verb(&mut self, verb: Value) -> &mut Self {
-        assert!(verb <= 99);
-        self.memory.set(2, verb);
-        self
+    /// Return the data flow of input values tracked so far
+    pub fn data_flow(&self) -> Option<&DataFlow<V>> {
+        self.flow.as_ref()
     }
 
-    /// Set stream that yields input values for the vm
-    pub fn input(&mut self, input: impl Stream<Item = Value> + Unpin + 'static) -> &mut Self {
-        self.input = Some(Box::new(input));
+    /// Enable logging of the given number of most recent steps for stepping backwards
+    ///
+    /// Stepping back restores registers, memory and the tracked data flow. Input values read by
+    /// undone steps are read again when running forward, while output values that were sent can't
+    /// be taken back (they are sent again when running forward). Events of a replayed session
+    /// consumed by undone steps are replayed again, and events recorded by undone steps are removed
+    /// from the recorded session. Input, output and replayed session detached by halting the
+    /// program are not restored.
+    pub fn undo_log(&mut self, capacity: usize) -> &mut Self {
+        self.undo = Some(UndoLog::new(capacity));
         self
     }
 
-    /// Run one program step
-    pub async fn step(&mut self) {
-        let instruction = Instruction::parse(self.memory.get_slice(self.ip, 4));
-        instruction.execute(self).await;
+    /// Undo the most recent step. Returns false if there is no logged step left to undo.
+    pub fn step_back(&mut self) -> bool {
+        match self.undo.as_mut().and_then(UndoLog::pop) {
+            Some(entry) => {
+                self.undo_step(entry);
+                true
+            }
+            None => false,
+        }
     }
 
-    /// Run program (run steps until done)
-    pub async fn run(&mut self) {
-        while !self.done {
-            self.step().await;
+    /// Step back until the most recent instruction that wrote to the given address is undone (so
+    /// that it is the next instruction). Returns false if no logged step wrote to the address.
+    pub fn run_back_to_write(&mut self, addr: Address) -> bool {
+        while let Some(entry) = self.undo.as_mut().and_then(UndoLog::pop) {
+            let writes = entry.writes(addr, &self.machine.memory);
+            self.undo_step(entry);
+            if writes {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Step back until the most recent output instruction is undone (so that it is the next
+    /// instruction). Returns false if no logged step wrote an output value.
+    pub fn rewind_to_output(&mut self) -> bool {
+        while let Some(entry) = self.undo.as_mut().and_then(UndoLog::pop) {
+            let output = entry.output;
+            self.undo_step(entry);
+            if output {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Run program (run steps until done) and return execution statistics of this run
+    ///
+    /// If a step fails, input and output channels are closed (so that connected consumers don't
+    /// wait forever) and the error is returned. Running the vm again without setting new channels
+    /// fails with `Error::Input` or `Error::Output` once the program needs them. The vm regularly yields to the executor, so other
+    /// tasks (like timers or other vms) can make progress even if the program never waits for
+    /// input or output.
+    pub async fn run(&mut self) -> Result<Report, Error> {
+        let start = Instant::now();
+        let (steps, inputs, outputs) = (self.steps, self.inputs, self.outputs);
+        self.max_address = None;
+        while !self.machine.done {
+            if let Err(err) = self.step().await {
+                debug!(ip = self.machine.ip, error = %err, "Program failed");
+                self.input = None;
+                self.output = None;
+                return Err(err);
+            }
+            if (self.steps - steps).is_multiple_of(Self::YIELD_INTERVAL) {
+                runtime::yield_now().await;
+            }
+        }
+        Ok(Report {
+            steps: self.steps - steps,
+            inputs: self.inputs - inputs,
+            outputs: self.outputs - outputs,
+            ip: self.machine.ip,
+            max_address: self.max_address,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// Run program until done, or until the given token is cancelled or times out
+    ///
+    /// An interrupted vm keeps its state and can be resumed by running it again.
+    pub async fn run_with(&mut self, token: &CancellationToken) -> Result<Report, Error> {
+        token.run(self.run()).await
+    }
+
+    /// Run program on a separate task (which may run on another thread)
+    ///
+    /// The returned handle resolves to the vm (so that its final state can be inspected) together
+    /// with the result of running it.
+    pub fn spawn(self) -> JoinHandle<(Self, Result<Report, Error>)> {
+        self.spawn_with(CancellationToken::new())
+    }
+
+    /// Run program on a separate task until done, or until the given token is cancelled or times
+    /// out (see `Vm::spawn` and `Vm::run_with`)
+    pub fn spawn_with(
+        mut self,
+        token: CancellationToken,
+    ) -> JoinHandle<(Self, Result<Report, Error>)> {
+        runtime::spawn(
+            async move {
+                let result = self.run_with(&token).await;
+                (self, result)
+            }
+            .in_current_span(),
+        )
+    }
+
+    /// Run all given vms on separate tasks until all are done and return their run reports
+    ///
+    /// The vms are taken out of the vector while running and put back in the same order
+    /// afterwards. If a vm fails, all other vms are cancelled and the error is returned. The
+    /// given token can be used to cancel all vms or make them time out. Every vm runs in a `vm`
+    /// tracing span with its index in the vector.
+    ///
+    /// If the returned future is dropped before it completes (e.g. when it is run with a
+    /// cancellation token itself), all vms are cancelled and dropped with their tasks, so the
+    /// vector stays empty. Use the `token` argument to interrupt vms and keep them.
+    pub async fn run_all(
+        vms: &mut Vec<Self>,
+        token: &CancellationToken,
+    ) -> Result<Vec<Report>, Error> {
+        // Token to cancel the remaining vms if one of them fails
+        let failed = CancellationToken::new();
+        // Don't leave the vms running detached if this future is dropped
+        let _guard = failed.drop_guard();
+        let tasks = vms.drain(..).enumerate().map(|(index, mut vm)| {
+            let token = token.clone();
+            let failed = failed.clone();
+            runtime::spawn(
+                async move {
+                    let result = failed.run(vm.run_with(&token)).await;
+                    if result.is_err() {
+                        failed.cancel();
+                    }
+                    (vm, result)
+                }
+                .instrument(debug_span!("vm", index)),
+            )
+        });
+        let mut reports = Vec::new();
+        let mut error = None;
+        for (vm, result) in future::join_all(tasks.collect::<Vec<_>>()).await {
+            vms.push(vm);
+            match result {
+                Ok(report) => reports.push(report),
+                // Prefer the error that caused the cancellation of the other vms (or the closing
+                // of their output channels)
+                Err(err) => match error {
+                    None | Some(Error::Cancelled) | Some(Error::Output { .. }) => error = Some(err),
+                    Some(_) => (),
+                },
+            }
+        }
+        match error {
+            Some(err) => Err(err),
+            None => Ok(reports),
         }
     }
 
     /// Run program and collect output into a vector
-    pub async fn run_and_collect(&mut self) -> Vec<Value> {
+    pub async fn run_and_collect(&mut self) -> Result<Vec<V>, Error> {
         let rx = self.output();
-        self.run().join(rx.collect()).await.1
+        let (result, output) = future::join(self.run(), rx.collect::<Vec<V>>()).await;
+        result.map(|_| output)
     }
 
     /// Return a stream that yields output values of the vm
-    pub fn output(&mut self) -> impl Stream<Item = Value> + Unpin + 'static {
+    pub fn output(&mut self) -> impl Stream<Item = V> + Unpin + 'static {
         assert!(self.output.is_none(), "Output stream already set");
         let (tx, rx) = channel::bounded(1);
         self.output = Some(tx);
         rx
     }
 
+    /// Return a snapshot of the memory and registers
+    pub fn state(&self) -> State<V> {
+        State {
+            memory: self.machine.memory.clone(),
+            ip: self.machine.ip,
+            relative_base: self.machine.relative_base,
+            steps: self.steps,
+            done: self.machine.done,
+        }
+    }
+
+    /// Restore memory and registers from the given snapshot
+    ///
+    /// The undo log (if enabled) is cleared, since it doesn't apply to the restored state.
+    pub fn restore(&mut self, state: State<V>) -> &mut Self {
+        self.machine.memory = state.memory;
+        self.machine.ip = state.ip;
+        self.machine.relative_base = state.relative_base;
+        self.steps = state.steps;
+        self.machine.done = state.done;
+        if let Some(undo) = self.undo.as_mut() {
+            undo.clear();
+        }
+        self.rewound_inputs.clear();
+        self
+    }
+
+    /// Return the instruction pointer (address of next instruction)
+    pub fn ip(&self) -> Address {
+        self.machine.ip
+    }
+
+    /// Return the relative base
+    pub fn relative_base(&self) -> Address {
+        self.machine.relative_base
+    }
+
+    /// Return the number of steps executed so far
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Return true if the program is done
+    pub fn is_done(&self) -> bool {
+        self.machine.done
+    }
+
+    /// Return true if input values are taken from a source other than the input stream
+    ///
+    /// This is the case if input values were taken back by stepping back, or while a session is
+    /// replayed (an input instruction then either reads the recorded value or fails with a
+    /// mismatch). Input instructions never wait for the input stream in this case.
+    pub fn has_pending_input(&self) -> bool {
+        !self.rewound_inputs.is_empty() || self.replay.is_some()
+    }
+
     /// Return a reference to the memory
-    pub fn memory(&self) -> &Memory {
-        &self.memory
+    pub fn memory(&self) -> &Memory<V> {
+        &self.machine.memory
+    }
+
+    /// Return a mutable reference to the memory (e.g. to patch a program before running it)
+    ///
+    /// Writes through this reference are not recorded in the undo log or the data flow.
+    pub fn memory_mut(&mut self) -> &mut Memory<V> {
+        &mut self.machine.memory
     }
 
     /// Return result (value at memory address 0)
-    pub fn result(&self) -> Value {
-        self.memory.get(0)
+    pub fn result(&self) -> V {
+        self.machine.memory.get(0)
     }
 }
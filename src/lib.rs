@@ -5,4 +5,12 @@
 mod input;
 pub use input::Input;
 
+mod logging;
+pub use logging::{init_logging, LOG_ENV};
+
+mod runtime;
+pub use runtime::JoinHandle;
+
 pub mod intcode;
+
+pub mod ffi;
@@ -0,0 +1,20 @@
+//! Advent of Code 2019: logging setup for binaries
+
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable to control logging
+pub const LOG_ENV: &str = "AOC_LOG";
+
+/// Set up logging of library events to stderr
+///
+/// Logging is controlled by the `AOC_LOG` environment variable, which takes filter directives
+/// like `RUST_LOG` does (e.g. `debug` for I/O events, `trace` for every executed instruction, or
+/// `advent_of_code_2019::input=debug`). Only warnings are logged by default.
+pub fn init_logging() {
+    let filter = EnvFilter::try_from_env(LOG_ENV).unwrap_or_else(|_| EnvFilter::new("warn"));
+    // Ignore failure if a global subscriber was already set up
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .try_init();
+}
@@ -0,0 +1,216 @@
+//! Advent of Code 2019: async runtime selection
+//!
+//! Runtime specific functionality (spawning tasks, timers, blocking on futures and reading
+//! files) is selected by cargo features: `runtime-async-std` (default) or `runtime-tokio`. Without
+//! any runtime feature, only `futures` is used: spawned tasks run when their handle is polled,
+//! timers use a helper thread and files are read synchronously. If both runtime features are
+//! enabled, async-std is used.
+
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{fmt, io};
+
+#[cfg(feature = "runtime-async-std")]
+mod imp {
+    use super::*;
+
+    pub type Task<T> = async_std::task::JoinHandle<T>;
+
+    pub type File = async_std::fs::File;
+
+    pub fn spawn<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> Task<T> {
+        async_std::task::spawn(future)
+    }
+
+    pub fn poll_task<T>(task: &mut Task<T>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(task).poll(cx)
+    }
+
+    pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+        async_std::task::block_on(future)
+    }
+
+    pub async fn sleep(duration: Duration) {
+        async_std::task::sleep(duration).await
+    }
+
+    pub async fn open(path: &Path) -> io::Result<File> {
+        File::open(path).await
+    }
+}
+
+#[cfg(all(feature = "runtime-tokio", not(feature = "runtime-async-std")))]
+mod imp {
+    use super::*;
+    use std::panic;
+    use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+    pub type Task<T> = tokio::task::JoinHandle<T>;
+
+    pub type File = Compat<tokio::fs::File>;
+
+    pub fn spawn<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> Task<T> {
+        tokio::task::spawn(future)
+    }
+
+    pub fn poll_task<T>(task: &mut Task<T>, cx: &mut Context<'_>) -> Poll<T> {
+        Pin::new(task).poll(cx).map(|result| match result {
+            Ok(value) => value,
+            Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+            Err(err) => panic!("Task failed: {}", err),
+        })
+    }
+
+    pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("Failed to create runtime")
+            .block_on(future)
+    }
+
+    pub async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+
+    pub async fn open(path: &Path) -> io::Result<File> {
+        Ok(tokio::fs::File::open(path).await?.compat())
+    }
+}
+
+#[cfg(not(any(feature = "runtime-async-std", feature = "runtime-tokio")))]
+mod imp {
+    use super::*;
+    use futures_util::io::AllowStdIo;
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+    use std::{fs, pin, thread};
+
+    pub type Task<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+    pub type File = AllowStdIo<fs::File>;
+
+    pub fn spawn<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> Task<T> {
+        Box::pin(future)
+    }
+
+    pub fn poll_task<T>(task: &mut Task<T>, cx: &mut Context<'_>) -> Poll<T> {
+        task.as_mut().poll(cx)
+    }
+
+    /// Waker that unparks the thread blocking on a future
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    pub fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    pub async fn sleep(duration: Duration) {
+        let (tx, rx) = async_channel::bounded::<()>(1);
+        thread::spawn(move || {
+            thread::sleep(duration);
+            drop(tx);
+        });
+        // Nothing is ever sent, so receiving only returns once the sender is dropped
+        let _ = rx.recv().await;
+    }
+
+    pub async fn open(path: &Path) -> io::Result<File> {
+        Ok(AllowStdIo::new(fs::File::open(path)?))
+    }
+}
+
+/// File reader of the selected runtime
+pub(crate) type File = imp::File;
+
+/// Handle of a spawned task, which resolves to the output of the task
+///
+/// With the tokio runtime, a panic of the task is propagated when awaiting the handle. Without a
+/// runtime, the task only makes progress while the handle is polled.
+pub struct JoinHandle<T>(imp::Task<T>);
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle").finish()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        imp::poll_task(&mut self.0, cx)
+    }
+}
+
+/// Spawn the given future as a new task
+///
+/// With the tokio runtime, this must be called from within a tokio runtime.
+pub(crate) fn spawn<T: Send + 'static>(
+    future: impl Future<Output = T> + Send + 'static,
+) -> JoinHandle<T> {
+    JoinHandle(imp::spawn(future))
+}
+
+/// Run the given future on the current thread until it completes
+pub(crate) fn block_on<T>(future: impl Future<Output = T>) -> T {
+    imp::block_on(future)
+}
+
+/// Wait for the given duration
+pub(crate) async fn sleep(duration: Duration) {
+    imp::sleep(duration).await
+}
+
+/// Open the given file for reading
+pub(crate) async fn open(path: &Path) -> io::Result<File> {
+    imp::open(path).await
+}
+
+/// Yield to the executor once, so that other tasks can make progress
+pub(crate) async fn yield_now() {
+    /// Future that is pending once (waking itself up immediately)
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    YieldNow(false).await
+}
+
+#[cfg(all(test, feature = "runtime-tokio", not(feature = "runtime-async-std")))]
+pub(crate) use tokio::test;
+
+#[cfg(all(
+    test,
+    not(all(feature = "runtime-tokio", not(feature = "runtime-async-std")))
+))]
+pub(crate) use async_std::test;
@@ -0,0 +1,58 @@
+// Test program for the Intcode VM C API (compiled and run by tests/ffi.rs)
+
+#include <stdio.h>
+#include <string.h>
+
+#include "intcode.h"
+
+#define CHECK(cond)                                                     \
+    if (!(cond)) {                                                      \
+        fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+        return 1;                                                       \
+    }
+
+int main(void) {
+    // Double input values until a zero is read
+    const int64_t program[] = {3, 15, 1006, 15, 14, 102, 2, 15, 15, 4, 15, 1105, 1, 0, 99, 0};
+    IntcodeVm *vm = intcode_new(program, sizeof(program) / sizeof(program[0]));
+    CHECK(vm != NULL);
+    CHECK(intcode_memory_size(vm) == 16);
+    CHECK(intcode_last_error(vm) == NULL);
+
+    int64_t value = 0;
+    CHECK(intcode_run(vm) == INTCODE_NEED_INPUT);
+    CHECK(intcode_ip(vm) == 0);
+    CHECK(!intcode_pop_output(vm, &value));
+    intcode_push_input(vm, 21);
+    CHECK(intcode_step(vm) == INTCODE_OK);
+    CHECK(intcode_read(vm, 15, &value) && value == 21);
+    CHECK(intcode_run(vm) == INTCODE_OUTPUT);
+    CHECK(intcode_pop_output(vm, &value) && value == 42);
+    CHECK(intcode_run(vm) == INTCODE_NEED_INPUT);
+    intcode_push_input(vm, 0);
+    CHECK(intcode_run(vm) == INTCODE_HALTED);
+    CHECK(intcode_step(vm) == INTCODE_HALTED);
+    CHECK(!intcode_read(vm, 16, &value));
+    intcode_free(vm);
+
+    // Patch memory before running and report errors
+    const int64_t failing[] = {1101, 1, 2, 0, 99};
+    vm = intcode_new(failing, 5);
+    CHECK(intcode_write(vm, 4, 42));
+    CHECK(!intcode_write(vm, 5, 42));
+    CHECK(intcode_run(vm) == INTCODE_ERROR);
+    CHECK(intcode_read(vm, 0, &value) && value == 3);
+    CHECK(intcode_last_error(vm) != NULL && strstr(intcode_last_error(vm), "42") != NULL);
+    intcode_free(vm);
+
+    // Writing beyond the end of memory fails
+    const int64_t overflowing[] = {109, 100, 21101, 1, 2, 0, 99};
+    vm = intcode_new(overflowing, 7);
+    CHECK(intcode_run(vm) == INTCODE_ERROR);
+    CHECK(intcode_ip(vm) == 2);
+    CHECK(intcode_last_error(vm) != NULL &&
+          strstr(intcode_last_error(vm), "Invalid address 100") != NULL);
+    intcode_free(vm);
+
+    return 0;
+}
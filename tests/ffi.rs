@@ -0,0 +1,192 @@
+//! Generate the C header and compile and run the C test program against the dynamic library
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Path to the C API module
+const SOURCE: &str = "src/ffi.rs";
+
+/// Path to the C header generated from the C API module
+const HEADER: &str = "include/intcode.h";
+
+/// Convert a Rust type of the C API to a C type
+fn c_type(ty: &str) -> String {
+    let ty = ty.trim();
+    if let Some(ty) = ty.strip_prefix("*const ") {
+        format!("const {} *", c_type(ty))
+    } else if let Some(ty) = ty.strip_prefix("*mut ") {
+        format!("{} *", c_type(ty))
+    } else {
+        match ty {
+            "i64" => "int64_t",
+            "usize" => "size_t",
+            "bool" => "bool",
+            "c_char" => "char",
+            "" => "void",
+            _ if ty.starts_with("Intcode") => ty,
+            _ => panic!("Unsupported type '{}' in C API", ty),
+        }
+        .to_string()
+    }
+}
+
+/// Declare a C variable or function of the given (Rust) type
+fn c_declaration(ty: &str, name: &str) -> String {
+    let ty = c_type(ty);
+    if ty.ends_with('*') {
+        format!("{}{}", ty, name)
+    } else {
+        format!("{} {}", ty, name)
+    }
+}
+
+/// Convert a CamelCase enum variant name to an UPPER_SNAKE_CASE constant name
+fn c_constant(prefix: &str, name: &str) -> String {
+    let mut constant = prefix.to_string();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            constant.push('_');
+        }
+        constant.push(c.to_ascii_uppercase());
+    }
+    constant
+}
+
+/// Append doc comments as C comments with the given indentation
+fn push_docs(out: &mut String, docs: &mut Vec<String>, indent: &str) {
+    for line in docs.drain(..) {
+        if line.is_empty() {
+            out.push_str(&format!("{}//\n", indent));
+        } else {
+            out.push_str(&format!("{}// {}\n", indent, line.trim_start_matches("# ")));
+        }
+    }
+}
+
+/// Generate the C header from the C API module
+fn generate(source: &str) -> String {
+    let mut out = String::from(
+        "// Intcode VM C API (generated from src/ffi.rs by tests/ffi.rs, do not edit)\n\n\
+         #ifndef INTCODE_H\n#define INTCODE_H\n\n\
+         #include <stdbool.h>\n#include <stddef.h>\n#include <stdint.h>\n\n\
+         #ifdef __cplusplus\nextern \"C\" {\n#endif\n",
+    );
+    let mut docs = Vec::new();
+    let mut lines = source.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.trim().to_string());
+        } else if line.starts_with("#[") {
+            continue;
+        } else if let Some(name) = line.strip_prefix("pub struct ") {
+            let name = name.trim_end_matches(" {");
+            out.push('\n');
+            push_docs(&mut out, &mut docs, "");
+            out.push_str(&format!("typedef struct {} {};\n", name, name));
+        } else if let Some(name) = line.strip_prefix("pub enum ") {
+            let name = name.trim_end_matches(" {");
+            out.push('\n');
+            push_docs(&mut out, &mut docs, "");
+            out.push_str("typedef enum {\n");
+            for line in lines.by_ref().take_while(|line| *line != "}") {
+                if let Some(doc) = line.strip_prefix("///") {
+                    docs.push(doc.trim().to_string());
+                } else {
+                    let line = line.trim_end_matches(',');
+                    let pos = line
+                        .find(" = ")
+                        .expect("Enum variants need explicit values");
+                    let (variant, value) = line.split_at(pos);
+                    push_docs(&mut out, &mut docs, "    ");
+                    out.push_str(&format!(
+                        "    {}{},\n",
+                        c_constant("INTCODE", variant),
+                        value
+                    ));
+                }
+            }
+            out.push_str(&format!("}} {};\n", name));
+        } else if line.starts_with("pub unsafe extern \"C\" fn ") {
+            // Collect signature, which may be wrapped over several lines
+            let mut signature = line.to_string();
+            while !signature.ends_with('{') {
+                signature.push_str(lines.next().expect("Unexpected end of signature"));
+            }
+            let signature = signature["pub unsafe extern \"C\" fn ".len()..].trim_end_matches('{');
+            let (name, rest) = signature.split_at(signature.find('(').unwrap());
+            let (params, ret) = rest[1..].split_at(rest.find(')').unwrap() - 1);
+            let params = params
+                .split(',')
+                .map(str::trim)
+                .filter(|param| !param.is_empty())
+                .map(|param| {
+                    let (name, ty) = param.split_at(param.find(':').unwrap());
+                    c_declaration(&ty[1..], name)
+                })
+                .collect::<Vec<_>>();
+            let params = if params.is_empty() {
+                "void".to_string()
+            } else {
+                params.join(", ")
+            };
+            let ret = ret[1..].trim().trim_start_matches("->");
+            out.push('\n');
+            push_docs(&mut out, &mut docs, "");
+            out.push_str(&format!("{}({});\n", c_declaration(ret, name), params));
+        } else {
+            docs.clear();
+        }
+    }
+    out.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n#endif\n");
+    out
+}
+
+#[test]
+fn header_up_to_date() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let source = fs::read_to_string(manifest_dir.join(SOURCE)).unwrap();
+    let header = generate(&source);
+    let path = manifest_dir.join(HEADER);
+    if env::var_os("INTCODE_UPDATE_HEADER").is_some() {
+        fs::write(&path, &header).unwrap();
+    }
+    assert!(
+        fs::read_to_string(&path).unwrap() == header,
+        "C header {} is outdated (run tests with INTCODE_UPDATE_HEADER=1 to update it)",
+        HEADER
+    );
+}
+
+#[test]
+fn c_api() {
+    // The dynamic library is placed next to the deps directory this test is built into
+    let lib_dir = env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|deps| deps.parent())
+        .map(PathBuf::from)
+        .unwrap();
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    let status = Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(manifest_dir.join("tests/ffi.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-ladvent_of_code_2019")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-o")
+        .arg(&program)
+        .status()
+        .expect("Failed to run C compiler");
+    assert!(status.success(), "Compiling C test program failed");
+    let status = Command::new(&program)
+        .status()
+        .expect("Failed to run C test program");
+    assert!(status.success(), "C test program failed");
+}